@@ -19,8 +19,36 @@ impl fmt::Display for TimePoint {
     }
 }
 
+/// 缩放之后的字号，不缩放时使用 style 中的字号
+struct FontSize {
+    font_size: u32,
+    scale: Option<f64>,
+}
+impl fmt::Display for FontSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.scale {
+            Some(scale) => write!(f, "\\fs{}", (self.font_size as f64 * scale).round()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 不透明度转换为 ASS 的 `\alpha`，00 为不透明，FF 为透明
+struct AssAlpha(f64);
+impl fmt::Display for AssAlpha {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "\\alpha&H{:02X}&",
+            ((1.0 - self.0) * 255.0).round() as u8
+        )
+    }
+}
+
 struct AssEffect {
     effect: DrawEffect,
+    /// 弹幕显示的时间，单位为秒
+    duration: f64,
 }
 impl fmt::Display for AssEffect {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -30,9 +58,54 @@ impl fmt::Display for AssEffect {
                 let (x1, y1) = end;
                 write!(f, "\\move({x0}, {y0}, {x1}, {y1})")
             }
-            DrawEffect::Fixed {} => {
-                error!("应该不会出现固定弹幕的");
-                fmt::Result::Err(fmt::Error)
+            DrawEffect::Boxed { pos, padding, .. } => {
+                let (x, y) = pos;
+                write!(f, "\\pos({}, {})", x + padding, y + padding)
+            }
+            DrawEffect::Fixed { pos } => {
+                let (x, y) = pos;
+                write!(f, "\\pos({x}, {y})")
+            }
+            DrawEffect::Advanced {
+                start,
+                end,
+                move_time: (t0, t1),
+                alpha: (a0, a1),
+                rotate: (rotate_z, rotate_y),
+                stroke,
+                ref font,
+            } => {
+                let (x0, y0) = start;
+                let (x1, y1) = end;
+                if start == end {
+                    write!(f, "\\pos({x0}, {y0})")?;
+                } else {
+                    write!(f, "\\move({x0}, {y0}, {x1}, {y1}, {t0}, {t1})")?;
+                }
+                // 哔哩哔哩的 Z 轴旋转是顺时针，ASS 是逆时针
+                if rotate_z != 0.0 {
+                    write!(f, "\\frz{}", -rotate_z)?;
+                }
+                if rotate_y != 0.0 {
+                    write!(f, "\\fry{rotate_y}")?;
+                }
+                if !stroke {
+                    write!(f, "\\bord0")?;
+                }
+                if let Some(font) = font {
+                    write!(f, "\\fn{font}")?;
+                }
+                // 淡入淡出到完全透明时用 \fad，否则用 \t 渐变
+                let duration_ms = (self.duration * 1000.0).round() as u32;
+                if a0 == a1 {
+                    write!(f, "{}", AssAlpha(a0))
+                } else if a1 == 0.0 {
+                    write!(f, "{}\\fad(0, {duration_ms})", AssAlpha(a0))
+                } else if a0 == 0.0 {
+                    write!(f, "{}\\fad({duration_ms}, 0)", AssAlpha(a1))
+                } else {
+                    write!(f, "{}\\t({})", AssAlpha(a0), AssAlpha(a1))
+                }
             }
         }
     }
@@ -57,23 +130,71 @@ impl super::CanvasConfig {
             format!(
                 "Style: Bottom,{font},{font_size},&H{a:02x}FFFFFF,&H00FFFFFF,&H{a:02x}000000,&H00000000,\
                 {bold}, 0, 0, 0, 100, 100, 0.00, 0.00, 1, \
-                {outline}, 0, 7, 0, 0, 0, 1",
+                {outline}, 0, 2, 0, 0, 0, 1",
                 a = self.opacity,
                 font = self.font,
                 font_size = self.font_size,
                 bold = self.bold as u8,
                 outline = self.outline,
             ),
+            // 字幕弹幕不透明，和普通字幕一样显示在底部居中
+            format!(
+                "Style: Subtitle,{font},{font_size},&H00FFFFFF,&H00FFFFFF,&H00000000,&H00000000,\
+                {bold}, 0, 0, 0, 100, 100, 0.00, 0.00, 1, \
+                {outline}, 0, 2, 0, 0, 0, 1",
+                font = self.font,
+                font_size = self.font_size,
+                bold = self.bold as u8,
+                outline = self.outline,
+            ),
             format!(
                 "Style: Top,{font},{font_size},&H{a:02x}FFFFFF,&H00FFFFFF,&H{a:02x}000000,&H00000000,\
                 {bold}, 0, 0, 0, 100, 100, 0.00, 0.00, 1, \
-                {outline}, 0, 7, 0, 0, 0, 1",
+                {outline}, 0, 8, 0, 0, 0, 1",
+                a = self.opacity,
+                font = self.font,
+                font_size = self.font_size,
+                bold = self.bold as u8,
+                outline = self.outline,
+            ),
+            format!(
+                "Style: Gift,{font},{font_size},&H{a:02x}FFFFFF,&H00FFFFFF,&H{a:02x}000000,&H00000000,\
+                {bold}, 0, 0, 0, 100, 100, 0.00, 0.00, 1, \
+                {outline}, 0, 3, 0, 0, 0, 1",
                 a = self.opacity,
                 font = self.font,
                 font_size = self.font_size,
                 bold = self.bold as u8,
                 outline = self.outline,
             ),
+            // 高级弹幕的位置和透明度由弹幕本身指定
+            format!(
+                "Style: Advanced,{font},{font_size},&H00FFFFFF,&H00FFFFFF,&H00000000,&H00000000,\
+                {bold}, 0, 0, 0, 100, 100, 0.00, 0.00, 1, \
+                {outline}, 0, 7, 0, 0, 0, 1",
+                font = self.font,
+                font_size = self.font_size,
+                bold = self.bold as u8,
+                outline = self.outline,
+            ),
+            // 醒目留言的背景框单独绘制，这里不需要描边
+            format!(
+                "Style: SuperChat,{font},{font_size},&H00FFFFFF,&H00FFFFFF,&H00000000,&H00000000,\
+                {bold}, 0, 0, 0, 100, 100, 0.00, 0.00, 1, \
+                0, 0, 7, 0, 0, 0, 1",
+                font = self.font,
+                font_size = self.font_size,
+                bold = self.bold as u8,
+            ),
+            // 互动弹幕的卡片和醒目留言一样，背景框单独绘制
+            format!(
+                "Style: Command,{font},{font_size},&H00FFFFFF,&H00FFFFFF,&H00000000,&H00000000,\
+                {bold}, 0, 0, 0, 100, 100, 0.00, 0.00, 1, \
+                0, 0, 7, 0, 0, 0, 1",
+                font = self.font,
+                font_size = self.font_size,
+                bold = self.bold as u8,
+            ),
         ]
     }
 }
@@ -144,10 +265,30 @@ impl<W: Write> AssWriter<W> {
     }
 
     pub fn write(&mut self, drawable: Drawable) -> Result<()> {
+        if let DrawEffect::Boxed {
+            pos: (x, y),
+            size: (w, h),
+            background: (r, g, b),
+            ..
+        } = drawable.effect
+        {
+            // 用 ASS 绘图指令画背景框，放在文字下面一层
+            writeln!(
+                self.f,
+                "Dialogue: 1,{start},{end},{style},,0,0,0,,{{\\pos({x}, {y})\\c&H{b:02x}{g:02x}{r:02x}&\\p1}}m 0 0 l {w} 0 {w} {h} 0 {h}{{\\p0}}",
+                start = TimePoint {
+                    t: drawable.danmu.timeline_s
+                },
+                end = TimePoint {
+                    t: drawable.danmu.timeline_s + drawable.duration
+                },
+                style = drawable.style_name,
+            )?;
+        }
         writeln!(
             self.f,
             // Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
-            "Dialogue: 2,{start},{end},{style},,0,0,0,,{{{effect}\\c&H{b:02x}{g:02x}{r:02x}&}}{text}",
+            "Dialogue: 2,{start},{end},{style},,0,0,0,,{{{effect}\\c&H{b:02x}{g:02x}{r:02x}&{font_size}}}{text}",
             start = TimePoint {
                 t: drawable.danmu.timeline_s
             },
@@ -156,7 +297,12 @@ impl<W: Write> AssWriter<W> {
             },
             style = drawable.style_name,
             effect = AssEffect {
-                effect: drawable.effect
+                effect: drawable.effect,
+                duration: drawable.duration,
+            },
+            font_size = FontSize {
+                font_size: self.canvas_config.font_size,
+                scale: drawable.danmu.font_scale,
             },
             b = drawable.danmu.rgb.2,
             g = drawable.danmu.rgb.1,
@@ -168,6 +314,17 @@ impl<W: Write> AssWriter<W> {
     }
 }
 
+impl<W: Write> crate::writer::Writer for AssWriter<W> {
+    fn write(&mut self, drawable: Drawable) -> Result<()> {
+        AssWriter::write(self, drawable)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.f.flush()?;
+        Ok(())
+    }
+}
+
 fn escape_text(text: &str) -> Cow<'_, str> {
     let text = text.trim();
     if memchr::memchr(b'\n', text.as_bytes()).is_some() {
@@ -223,4 +380,25 @@ mod tests {
             r"呵\N呵\N比\N你\N们\N更\N喜\N欢\N晚\N晚"
         );
     }
+
+    #[test]
+    fn advanced_danmu() {
+        let xml = r#"<i><d p="1.5,7,25,16777215,0,0,0,0">["0.5","0.25","1-0",3,"文字",30,0,"0.25","0.75",500,1000,0]</d></i>"#;
+        let config = crate::canvas::tests::config();
+        let mut canvas = config.clone().canvas();
+        let mut writer = AssWriter::new(vec![], "test".to_string(), config).unwrap();
+        for danmu in crate::Parser::new(xml.as_bytes()) {
+            let drawable = canvas.draw(danmu.unwrap()).unwrap().unwrap();
+            writer.write(drawable).unwrap();
+        }
+        writer.f.flush().unwrap();
+        let ass = String::from_utf8(writer.f.get_ref().clone()).unwrap();
+        let line = ass.lines().last().unwrap();
+        assert!(line.starts_with("Dialogue: 2,0:00:01.50,0:00:04.50,Advanced,"));
+        assert!(line.contains(
+            r"{\move(640, 180, 364, 540, 1000, 1500)\frz-30\bord0\alpha&H00&\fad(0, 3000)"
+        ));
+        assert!(line.contains(r"\fs41}"));
+        assert!(line.ends_with("文字"));
+    }
 }
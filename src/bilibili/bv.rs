@@ -1,18 +1,19 @@
-use super::DanmakuElem;
+use super::{Client, DanmakuElem};
 use anyhow::{Context, Result};
 use prost::Message;
 
-const URL: &str = "http://api.bilibili.com/x/v2/dm/web/seg.so";
+const PATH: &str = "/x/v2/dm/web/seg.so";
 
 async fn get_danmu_for_cid_segment(
-    client: reqwest::Client,
+    client: &Client,
     cid: u64,
     segment: u64,
 ) -> Result<Vec<DanmakuElem>> {
     let resp = client
-        .get(URL)
-        .query(&[("oid", cid), ("segment_index", segment), ("type", 1)])
-        .send()
+        .get(
+            PATH,
+            &[("oid", cid), ("segment_index", segment), ("type", 1)],
+        )
         .await?;
     // code 304
     if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
@@ -37,32 +38,98 @@ async fn get_danmu_for_cid_segment(
         // parse as pb
         let content = resp.bytes().await?;
         let reply = super::DmSegMobileReply::decode(content).context("请求 body 无法解析为 PB")?;
-        Ok(reply.elems)
+        Ok(reply.into_elems())
     }
 }
 
-pub async fn get_danmu_for_video(cid: u64, duration_sec: u64) -> Result<Vec<DanmakuElem>> {
-    let client = reqwest::ClientBuilder::new()
-    .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/100.0.4896.60 Safari/537.36")
-    .build()?;
+/// 视频的弹幕，部分分段下载失败时仍然包含其他分段的弹幕
+#[derive(Debug, Default)]
+pub struct VideoDanmu {
+    pub elems: Vec<DanmakuElem>,
+    /// 重试之后仍然下载失败的分段，从 1 开始
+    pub failed_segments: Vec<u64>,
+    /// 互动弹幕和表情，需要单独通过 [`super::get_dm_view`] 获取
+    pub view: super::DmView,
+}
+
+impl VideoDanmu {
+    pub fn is_complete(&self) -> bool {
+        self.failed_segments.is_empty()
+    }
+}
 
+pub async fn get_danmu_for_video(
+    client: &Client,
+    cid: u64,
+    duration_sec: u64,
+) -> Result<VideoDanmu> {
     // segment 为 6 分钟一包，见 https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/danmaku/danmaku_proto.md
     let s = duration_sec;
+    let segments = s.div_ceil(360);
     info!("获取视频 aid={} 的弹幕，视频有 {} 秒", cid, s);
 
-    let mut fut = vec![];
-    for i in 0..s.div_ceil(360) {
-        fut.push(get_danmu_for_cid_segment(client.clone(), cid, i + 1));
+    // 并发由 client 的全局限速控制
+    let results = futures::future::join_all(
+        (1..=segments).map(|i| async move { (i, get_danmu_for_cid_segment(client, cid, i).await) }),
+    )
+    .await;
+
+    let mut danmu = VideoDanmu::default();
+    for (i, result) in results {
+        match result {
+            Ok(elems) => danmu.elems.extend(elems),
+            Err(e) => {
+                warn!("视频 cid={} 第 {} 个分段下载失败：{:?}", cid, i, e);
+                danmu.failed_segments.push(i);
+            }
+        }
     }
-    let mut results =
-        futures::future::try_join_all(fut)
-            .await?
-            .into_iter()
-            .fold(vec![], |mut acc, v| {
-                acc.extend(v);
-                acc
-            });
-    results.sort_unstable_by_key(|d| d.progress);
+    if segments > 0 && danmu.failed_segments.len() as u64 == segments {
+        anyhow::bail!("视频 cid={} 的 {} 个分段全部下载失败", cid, segments);
+    }
+    danmu.elems.sort_unstable_by_key(|d| d.progress);
+
+    Ok(danmu)
+}
 
-    Ok(results)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bilibili::client::tests::{client, mock_server};
+    use crate::bilibili::DmSegMobileReply;
+
+    #[tokio::test]
+    async fn partial_result() {
+        let base_url = mock_server(|path, _| {
+            if path.contains("segment_index=2") {
+                return (500, vec![]);
+            }
+            let elem = DanmakuElem {
+                id: 1,
+                progress: if path.contains("segment_index=1") {
+                    1000
+                } else {
+                    800_000
+                },
+                content: "弹幕".to_string(),
+                ..Default::default()
+            };
+            (
+                200,
+                DmSegMobileReply {
+                    elems: vec![elem],
+                    ai_flag: None,
+                }
+                .encode_to_vec(),
+            )
+        });
+        let danmu = get_danmu_for_video(&client(base_url), 1, 900)
+            .await
+            .unwrap();
+        assert_eq!(danmu.failed_segments, vec![2]);
+        assert_eq!(
+            danmu.elems.iter().map(|e| e.progress).collect::<Vec<_>>(),
+            vec![1000, 800_000]
+        );
+    }
 }
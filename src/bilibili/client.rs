@@ -0,0 +1,345 @@
+//! 请求哔哩哔哩接口的 http client，所有请求共用超时、失败重试以及全局限速
+use super::Credential;
+use anyhow::{Context, Result};
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/100.0.4896.60 Safari/537.36";
+
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// 接口地址，测试时可以指向本地的 mock server
+    pub base_url: String,
+    /// 直播接口地址
+    pub live_base_url: String,
+    /// 单个请求的超时时间
+    pub timeout: Duration,
+    /// 失败之后最多重试的次数
+    pub retries: u32,
+    /// 第一次重试前等待的时间，之后每次翻倍
+    pub backoff: Duration,
+    /// 每秒最多发出的请求数量，0 代表不限制
+    pub rate_limit: f64,
+    /// 登录凭据，所有请求都会带上
+    pub credential: Option<Credential>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://api.bilibili.com".to_string(),
+            live_base_url: "https://api.live.bilibili.com".to_string(),
+            timeout: Duration::from_secs(10),
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            rate_limit: 5.0,
+            credential: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    /// 不跟随跳转的 client，用于解析短链接，不带登录凭据
+    no_redirect: reqwest::Client,
+    config: Arc<ClientConfig>,
+    /// 下一个请求最早可以发出的时间
+    next_slot: Arc<Mutex<Instant>>,
+}
+
+impl Client {
+    pub fn new(config: ClientConfig) -> Result<Self> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(credential) = config.credential.as_ref() {
+            headers.insert(reqwest::header::COOKIE, credential.cookie_header());
+        }
+        let http = reqwest::ClientBuilder::new()
+            .user_agent(USER_AGENT)
+            .timeout(config.timeout)
+            .default_headers(headers)
+            .build()?;
+        let no_redirect = reqwest::ClientBuilder::new()
+            .user_agent(USER_AGENT)
+            .timeout(config.timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+        Ok(Self {
+            http,
+            no_redirect,
+            config: Arc::new(config),
+            next_slot: Arc::new(Mutex::new(Instant::now())),
+        })
+    }
+
+    async fn wait_rate_limit(&self) {
+        if self.config.rate_limit <= 0.0 {
+            return;
+        }
+        let interval = Duration::from_secs_f64(1.0 / self.config.rate_limit);
+        let wait = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let slot = (*next_slot).max(now);
+            *next_slot = slot + interval;
+            slot - now
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// GET `{base_url}{path}`，遇到 412/429/5xx、超时或者连接错误时按指数退避重试
+    pub async fn get<Q>(&self, path: &str, query: &Q) -> Result<reqwest::Response>
+    where
+        Q: Serialize + ?Sized,
+    {
+        let url = format!("{}{}", self.config.base_url.trim_end_matches('/'), path);
+        self.get_url(&self.http, url, query).await
+    }
+
+    /// GET 完整的 `url`，不跟随跳转，重试和限速与 [`Client::get`] 相同
+    pub async fn get_no_redirect(&self, url: &url::Url) -> Result<reqwest::Response> {
+        self.get_url(&self.no_redirect, url.to_string(), &()).await
+    }
+
+    /// GET `{base_url}{path}` 并解析 `{"code": 0, "data": ...}` 格式的返回
+    pub async fn get_json<T, Q>(&self, path: &str, query: &Q) -> Result<T>
+    where
+        T: DeserializeOwned,
+        Q: Serialize + ?Sized,
+    {
+        #[derive(Deserialize)]
+        struct BiliResponse<T> {
+            code: i64,
+            #[serde(default)]
+            message: String,
+            data: Option<T>,
+        }
+
+        let text = self.get(path, query).await?.text().await?;
+        let resp: BiliResponse<T> =
+            serde_json::from_str(&text).with_context(|| format!("{path} 的返回无法解析"))?;
+        if resp.code != 0 {
+            debug!("response text = {}", text);
+            anyhow::bail!("code = {}, message = {}", resp.code, resp.message);
+        }
+        resp.data
+            .with_context(|| format!("{path} 的返回中没有 data"))
+    }
+
+    /// 视频的信息，包括标题和分 p
+    pub async fn video_info(&self, bv: &str) -> Result<biliapi::requests::VideoInfo> {
+        self.get_json("/x/web-interface/view", &[("bvid", bv)])
+            .await
+            .with_context(|| format!("获取视频 {bv} 的信息失败"))
+    }
+
+    /// GET `{live_base_url}{path}`，重试和限速与 [`Client::get`] 相同
+    pub async fn get_live<Q>(&self, path: &str, query: &Q) -> Result<reqwest::Response>
+    where
+        Q: Serialize + ?Sized,
+    {
+        let url = format!(
+            "{}{}",
+            self.config.live_base_url.trim_end_matches('/'),
+            path
+        );
+        self.get_url(&self.http, url, query).await
+    }
+
+    async fn get_url<Q>(
+        &self,
+        http: &reqwest::Client,
+        url: String,
+        query: &Q,
+    ) -> Result<reqwest::Response>
+    where
+        Q: Serialize + ?Sized,
+    {
+        let mut attempt = 0;
+        loop {
+            self.wait_rate_limit().await;
+            let result = http.get(&url).query(query).send().await;
+            let error = match &result {
+                Ok(resp) if should_retry(resp.status()) => {
+                    Some(format!("status {}", resp.status()))
+                }
+                Ok(_) => None,
+                Err(e) if e.is_timeout() || e.is_connect() || e.is_request() => Some(e.to_string()),
+                Err(_) => None,
+            };
+            let Some(error) = error else {
+                return result.with_context(|| format!("请求 {url} 失败"));
+            };
+            if attempt >= self.config.retries {
+                anyhow::bail!(
+                    "请求 {} 失败，重试 {} 次之后仍然错误：{}",
+                    url,
+                    attempt,
+                    error
+                );
+            }
+            let delay = self.config.backoff * 2u32.pow(attempt);
+            warn!("请求 {} 失败（{}），{:?} 后重试", url, error, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// 412 是哔哩哔哩的风控，429 是请求过于频繁
+fn should_retry(status: StatusCode) -> bool {
+    status == StatusCode::PRECONDITION_FAILED
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status.is_server_error()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    pub(crate) struct MockResponse {
+        pub status: u16,
+        pub headers: Vec<(&'static str, String)>,
+        pub body: Vec<u8>,
+    }
+
+    impl From<(u16, Vec<u8>)> for MockResponse {
+        fn from((status, body): (u16, Vec<u8>)) -> Self {
+            Self {
+                status,
+                headers: vec![],
+                body,
+            }
+        }
+    }
+
+    /// 本地的 mock server，handler 的参数为请求的 path（带 query）和这是第几个请求，返回 status 和 body
+    pub(crate) fn mock_server<F>(handler: F) -> String
+    where
+        F: Fn(&str, usize) -> (u16, Vec<u8>) + Send + 'static,
+    {
+        mock_server_with_headers(move |path, i| handler(path, i).into())
+    }
+
+    /// 和 [`mock_server`] 相同，可以返回 header
+    pub(crate) fn mock_server_with_headers<F>(handler: F) -> String
+    where
+        F: Fn(&str, usize) -> MockResponse + Send + 'static,
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let count = AtomicUsize::new(0);
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                // 读完 header，GET 请求没有 body
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let path = request_line.split(' ').nth(1).unwrap_or_default();
+                let resp = handler(path, count.fetch_add(1, Ordering::SeqCst));
+                write!(stream, "HTTP/1.1 {} Mock\r\n", resp.status).unwrap();
+                for (name, value) in resp.headers.iter() {
+                    write!(stream, "{name}: {value}\r\n").unwrap();
+                }
+                write!(
+                    stream,
+                    "Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    resp.body.len()
+                )
+                .unwrap();
+                stream.write_all(&resp.body).unwrap();
+            }
+        });
+        format!("http://{addr}")
+    }
+
+    pub(crate) fn client(base_url: String) -> Client {
+        Client::new(ClientConfig {
+            live_base_url: base_url.clone(),
+            base_url,
+            backoff: Duration::from_millis(1),
+            rate_limit: 0.0,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn retry_with_backoff() {
+        let base_url = mock_server(|path, i| {
+            assert_eq!(path, "/test?a=1");
+            match i {
+                0 => (412, vec![]),
+                1 => (503, vec![]),
+                _ => (200, b"ok".to_vec()),
+            }
+        });
+        let resp = client(base_url.clone())
+            .get("/test", &[("a", 1)])
+            .await
+            .unwrap();
+        assert_eq!(resp.text().await.unwrap(), "ok");
+
+        let base_url = mock_server(|_, _| (429, vec![]));
+        let err = client(base_url).get("/test", &()).await.unwrap_err();
+        assert!(err.to_string().contains("重试 3 次"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn video_info() {
+        let base_url = mock_server(|path, i| {
+            assert_eq!(path, "/x/web-interface/view?bvid=BV1xx411c7mD");
+            if i == 0 {
+                return (412, vec![]);
+            }
+            let body = serde_json::json!({
+                "code": 0,
+                "message": "0",
+                "data": {
+                    "bvid": "BV1xx411c7mD", "aid": 2, "videos": 1, "title": "标题",
+                    "pubdate": 1600000000, "desc": "", "duration": 60, "pic": "",
+                    "stat": {
+                        "aid": 2, "view": 0, "danmaku": 0, "reply": 0, "favorite": 0,
+                        "coin": 0, "share": 0, "now_rank": 0, "his_rank": 0,
+                        "like": 0, "dislike": 0
+                    },
+                    "owner": {"mid": 1, "name": "UP", "face": ""},
+                    "pages": [{"cid": 3, "page": 1, "from": "vupload", "part": "P1", "duration": 60}]
+                }
+            });
+            (200, body.to_string().into_bytes())
+        });
+        let info = client(base_url).video_info("BV1xx411c7mD").await.unwrap();
+        assert_eq!(info.title, "标题");
+        assert_eq!(info.pages[0].cid, 3);
+
+        let base_url = mock_server(|_, _| (200, r#"{"code": -404, "message": "啥都木有"}"#.into()));
+        let err = client(base_url).video_info("BV1").await.unwrap_err();
+        assert!(format!("{err:#}").contains("-404"), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn rate_limit() {
+        let client = Client::new(ClientConfig {
+            rate_limit: 50.0,
+            ..Default::default()
+        })
+        .unwrap();
+        let t = Instant::now();
+        for _ in 0..5 {
+            client.wait_rate_limit().await;
+        }
+        assert!(t.elapsed() >= Duration::from_millis(80));
+    }
+}
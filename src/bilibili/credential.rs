@@ -0,0 +1,115 @@
+//! 登录凭据。匿名请求拿到的弹幕更少，部分内容需要登录才能获取
+use anyhow::{Context, Result};
+use reqwest::header::HeaderValue;
+use std::path::Path;
+
+/// 哔哩哔哩的 SESSDATA cookie，Debug 输出时会隐藏
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credential {
+    sessdata: String,
+}
+
+impl Credential {
+    /// 可以是 SESSDATA 的值，也可以带上 `SESSDATA=` 前缀
+    pub fn new(sessdata: &str) -> Result<Self> {
+        let sessdata = sessdata.trim();
+        let sessdata = sessdata.strip_prefix("SESSDATA=").unwrap_or(sessdata);
+        anyhow::ensure!(!sessdata.is_empty(), "SESSDATA 为空");
+        anyhow::ensure!(
+            sessdata
+                .bytes()
+                .all(|b| b.is_ascii_graphic() && b != b';' && b != b','),
+            "SESSDATA 中有不合法的字符"
+        );
+        Ok(Self {
+            sessdata: sessdata.to_string(),
+        })
+    }
+
+    /// 读取 cookie 文件，见 [`Credential::parse_cookies`]
+    pub fn from_cookie_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("读取 cookie 文件 {} 失败", path.display()))?;
+        Self::parse_cookies(&text)
+            .with_context(|| format!("cookie 文件 {} 中没有找到 SESSDATA", path.display()))
+    }
+
+    /// 支持浏览器插件导出的 Netscape 格式 cookies.txt、`Cookie` header 以及只有 SESSDATA 值的文本
+    pub fn parse_cookies(text: &str) -> Result<Self> {
+        for line in text.lines().map(str::trim) {
+            // cookies.txt 中 HttpOnly 的 cookie 以 #HttpOnly_ 开头
+            if line.is_empty() || (line.starts_with('#') && !line.starts_with("#HttpOnly_")) {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() == 7 {
+                if fields[5] == "SESSDATA" {
+                    return Self::new(fields[6]);
+                }
+                continue;
+            }
+            let line = line.strip_prefix("Cookie:").unwrap_or(line);
+            for cookie in line.split(';') {
+                if let Some(("SESSDATA", value)) = cookie.trim().split_once('=') {
+                    return Self::new(value);
+                }
+            }
+            if !line.contains('=') {
+                return Self::new(line);
+            }
+        }
+        anyhow::bail!("没有找到 SESSDATA")
+    }
+
+    /// `Cookie` header，标记为敏感信息，不会出现在 reqwest 的 Debug 输出中
+    pub(crate) fn cookie_header(&self) -> HeaderValue {
+        let mut value = HeaderValue::from_str(&format!("SESSDATA={}", self.sessdata))
+            .expect("SESSDATA 已经检查过");
+        value.set_sensitive(true);
+        value
+    }
+}
+
+impl std::str::FromStr for Credential {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::new(s)
+    }
+}
+
+impl std::fmt::Debug for Credential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Credential(SESSDATA=***)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cookies() {
+        let expected = Credential::new("abc%2C123*de").unwrap();
+        for text in [
+            "abc%2C123*de\n",
+            "SESSDATA=abc%2C123*de",
+            "Cookie: buvid3=xxx; SESSDATA=abc%2C123*de; bili_jct=yyy",
+            "# Netscape HTTP Cookie File\n\
+            .bilibili.com\tTRUE\t/\tFALSE\t1700000000\tbuvid3\txxx\n\
+            #HttpOnly_.bilibili.com\tTRUE\t/\tTRUE\t1700000000\tSESSDATA\tabc%2C123*de\n",
+        ] {
+            assert_eq!(Credential::parse_cookies(text).unwrap(), expected);
+        }
+        assert!(Credential::parse_cookies("buvid3=xxx").is_err());
+        assert!(Credential::new("a;b").is_err());
+    }
+
+    #[test]
+    fn redacted() {
+        let credential = Credential::new("secret").unwrap();
+        assert!(!format!("{credential:?}").contains("secret"));
+        assert!(credential.cookie_header().is_sensitive());
+        assert!(!format!("{:?}", credential.cookie_header()).contains("secret"));
+    }
+}
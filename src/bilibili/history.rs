@@ -0,0 +1,218 @@
+//! 历史弹幕。哔哩哔哩每天保存一份弹幕池的快照，`seg.so` 不会返回已经被挤出弹幕池的弹幕，需要登录才能获取
+use super::{Client, DanmakuElem};
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate};
+use prost::Message;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+const INDEX_PATH: &str = "/x/v2/dm/history/index";
+const SEGMENT_PATH: &str = "/x/v2/dm/web/history/seg.so";
+
+#[derive(Debug, Deserialize)]
+struct BiliResponse<T> {
+    code: i64,
+
+    #[serde(default)]
+    message: String,
+
+    /// 这个月没有历史弹幕时为 null
+    #[serde(default = "Option::default")]
+    data: Option<T>,
+}
+
+/// 解析 `2023-01-01` 或者 `2023-01-01..2023-01-31`，两端都包含
+pub fn parse_date_range(s: &str) -> Result<RangeInclusive<NaiveDate>> {
+    let parse = |s: &str| {
+        NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+            .with_context(|| format!("日期 {s} 应该是 YYYY-MM-DD 格式"))
+    };
+    let (start, end) = match s.split_once("..") {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => {
+            let date = parse(s)?;
+            (date, date)
+        }
+    };
+    anyhow::ensure!(start <= end, "开始日期 {} 晚于结束日期 {}", start, end);
+    Ok(start..=end)
+}
+
+/// 某个月中有历史弹幕的日期
+async fn get_history_dates(client: &Client, cid: u64, month: &str) -> Result<Vec<NaiveDate>> {
+    let resp = client
+        .get(
+            INDEX_PATH,
+            &[("type", "1"), ("oid", &cid.to_string()), ("month", month)],
+        )
+        .await?;
+    let text = resp.text().await?;
+    let resp: BiliResponse<Vec<String>> =
+        serde_json::from_str(&text).context("历史弹幕日期无法解析")?;
+    if resp.code != 0 {
+        debug!("response text = {}", text);
+        anyhow::bail!("code = {}, message = {}", resp.code, resp.message);
+    }
+    resp.data
+        .unwrap_or_default()
+        .iter()
+        .map(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").context("历史弹幕日期无法解析"))
+        .collect()
+}
+
+async fn get_history_danmu_for_date(
+    client: &Client,
+    cid: u64,
+    date: NaiveDate,
+) -> Result<Vec<DanmakuElem>> {
+    let resp = client
+        .get(
+            SEGMENT_PATH,
+            &[
+                ("type", "1"),
+                ("oid", &cid.to_string()),
+                ("date", &date.format("%Y-%m-%d").to_string()),
+            ],
+        )
+        .await?;
+    let is_json_resp = resp
+        .headers()
+        .get("content-type")
+        .map(|v| v.as_bytes().starts_with(b"application/json"))
+        .unwrap_or(false);
+    if is_json_resp {
+        // 没有登录等错误
+        biliapi::requests::BiliResponse::<()>::from_response(resp).await?;
+        anyhow::bail!("The response should fail");
+    }
+    let content = resp.bytes().await?;
+    let reply = super::DmSegMobileReply::decode(content).context("请求 body 无法解析为 PB")?;
+    Ok(reply.into_elems())
+}
+
+/// 获取日期范围内每一天的历史弹幕，按 id 去重。部分日期下载失败时仍然返回其他日期的弹幕
+pub async fn get_history_danmu_for_video(
+    client: &Client,
+    cid: u64,
+    dates: RangeInclusive<NaiveDate>,
+) -> Result<Vec<DanmakuElem>> {
+    let mut available = vec![];
+    let mut month = dates.start().with_day(1).unwrap();
+    while month <= *dates.end() {
+        let dates_in_month = get_history_dates(client, cid, &month.format("%Y-%m").to_string())
+            .await
+            .with_context(|| format!("获取 {} 的历史弹幕日期失败", month.format("%Y-%m")))?;
+        available.extend(dates_in_month.into_iter().filter(|d| dates.contains(d)));
+        month = month
+            .checked_add_months(chrono::Months::new(1))
+            .context("日期超出范围")?;
+    }
+    info!(
+        "视频 cid={} 在 {} 到 {} 之间有 {} 天的历史弹幕",
+        cid,
+        dates.start(),
+        dates.end(),
+        available.len()
+    );
+
+    let results =
+        futures::future::join_all(available.iter().map(|&date| async move {
+            (date, get_history_danmu_for_date(client, cid, date).await)
+        }))
+        .await;
+    let mut days = vec![];
+    let mut failed = 0;
+    for (date, result) in results {
+        match result {
+            Ok(elems) => days.push(elems),
+            Err(e) => {
+                warn!("视频 cid={} {} 的历史弹幕下载失败：{:?}", cid, date, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 && failed == available.len() {
+        anyhow::bail!("视频 cid={} 的历史弹幕全部下载失败", cid);
+    }
+    Ok(merge(days.into_iter().flatten()))
+}
+
+/// 合并多次下载的弹幕，按 `DanmakuElem::id` 去重，并按时间排序
+pub fn merge(elems: impl IntoIterator<Item = DanmakuElem>) -> Vec<DanmakuElem> {
+    let mut seen = HashSet::new();
+    let mut merged: Vec<_> = elems.into_iter().filter(|e| seen.insert(e.id)).collect();
+    merged.sort_by_key(|d| d.progress);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bilibili::client::tests::{client, mock_server};
+    use crate::bilibili::DmSegMobileReply;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn date_range() {
+        assert_eq!(
+            parse_date_range("2023-01-05").unwrap(),
+            date("2023-01-05")..=date("2023-01-05")
+        );
+        assert_eq!(
+            parse_date_range("2023-01-05..2023-02-01").unwrap(),
+            date("2023-01-05")..=date("2023-02-01")
+        );
+        assert!(parse_date_range("2023-02-01..2023-01-05").is_err());
+        assert!(parse_date_range("2023/01/05").is_err());
+    }
+
+    #[tokio::test]
+    async fn merge_history_days() {
+        let base_url = mock_server(|path, _| {
+            if path.starts_with(INDEX_PATH) {
+                let body = if path.contains("month=2023-01") {
+                    r#"{"code":0,"message":"0","data":["2023-01-30","2023-01-31"]}"#
+                } else {
+                    r#"{"code":0,"message":"0","data":null}"#
+                };
+                return (200, body.as_bytes().to_vec());
+            }
+            assert!(path.starts_with(SEGMENT_PATH), "{path}");
+            let elem = |id, progress| DanmakuElem {
+                id,
+                progress,
+                ..Default::default()
+            };
+            let elems = if path.contains("date=2023-01-30") {
+                vec![elem(1, 3000), elem(2, 1000)]
+            } else if path.contains("date=2023-01-31") {
+                vec![elem(2, 1000), elem(3, 2000)]
+            } else {
+                panic!("不在范围内的日期 {path}")
+            };
+            (
+                200,
+                DmSegMobileReply {
+                    elems,
+                    ai_flag: None,
+                }
+                .encode_to_vec(),
+            )
+        });
+        let elems = get_history_danmu_for_video(
+            &client(base_url),
+            1,
+            date("2023-01-30")..=date("2023-02-03"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            elems.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![2, 3, 1]
+        );
+    }
+}
@@ -1,8 +1,30 @@
 mod model;
-pub use model::{DanmakuElem, DmSegMobileReply};
+pub use model::{
+    CommandDm, DanmakuAIFlag, DanmakuElem, DanmakuFlag, DmSegMobileReply, DmWebViewReply,
+    Expression, Expressions, Period,
+};
+
+mod credential;
+pub use credential::Credential;
+
+pub(crate) mod client;
+pub use client::{Client, ClientConfig};
 
 mod bv;
-pub use bv::get_danmu_for_video;
+pub use bv::{get_danmu_for_video, VideoDanmu};
+
+mod history;
+pub use history::{get_history_danmu_for_video, merge, parse_date_range};
 
 mod season;
 pub use season::Season;
+
+mod view;
+pub use view::{get_dm_view, DmView};
+
+mod short_link;
+pub use short_link::resolve_short_link;
+
+mod xml;
+pub(crate) use xml::escape;
+pub use xml::write_xml;
@@ -3,32 +3,42 @@
 //! 可以看旁边的 dm.proto
 
 use prost::Message;
+use serde::{Deserialize, Deserializer};
 
 /// 弹幕 pb 定义
-#[derive(Clone, Message)]
+///
+/// 也可以从 JSON 反序列化，兼容 protobuf 的 JSON 映射（驼峰命名、int64 为字符串）
+#[derive(Clone, Message, Deserialize)]
+#[serde(default)]
 pub struct DanmakuElem {
     /// 弹幕 dmid
     #[prost(int64, tag = "1")]
+    #[serde(deserialize_with = "int_or_string")]
     pub id: i64,
 
     /// 弹幕出现位置（单位 ms）
     #[prost(int32, tag = "2")]
+    #[serde(deserialize_with = "int_or_string")]
     pub progress: i32,
 
     /// 弹幕类型
     #[prost(int32, tag = "3")]
+    #[serde(deserialize_with = "int_or_string")]
     pub mode: i32,
 
     /// 弹幕字号
     #[prost(int32, tag = "4")]
+    #[serde(deserialize_with = "int_or_string")]
     pub fontsize: i32,
 
     /// 弹幕颜色
     #[prost(uint32, tag = "5")]
+    #[serde(deserialize_with = "int_or_string")]
     pub color: u32,
 
     /// 发送者 mid hash
     #[prost(string, tag = "6")]
+    #[serde(alias = "midHash")]
     pub mid_hash: String,
 
     /// 弹幕正文
@@ -37,10 +47,12 @@ pub struct DanmakuElem {
 
     /// 弹幕发送时间
     #[prost(int64, tag = "8")]
+    #[serde(deserialize_with = "int_or_string")]
     pub ctime: i64,
 
-    /// 弹幕权重
+    /// 弹幕权重，JSON 中没有权重时为 -1，和权重 0 区分
     #[prost(int32, tag = "9")]
+    #[serde(deserialize_with = "int_or_string", default = "no_weight")]
     pub weight: i32,
 
     /// 动作？
@@ -49,21 +61,180 @@ pub struct DanmakuElem {
 
     /// 弹幕池
     #[prost(int32, tag = "11")]
+    #[serde(deserialize_with = "int_or_string")]
     pub pool: i32,
 
     /// 弹幕 dmid str
     #[prost(string, tag = "12")]
+    #[serde(alias = "idStr", alias = "id_str")]
     pub dmid_str: String,
 
     /// 弹幕属性
     #[prost(int32, tag = "13")]
+    #[serde(deserialize_with = "int_or_string")]
     pub attr: i32,
 }
 
-#[derive(Clone, Message)]
+/// 云屏蔽的 AI 评分
+#[derive(Clone, Message, Deserialize)]
+pub struct DanmakuFlag {
+    #[prost(int64, tag = "1")]
+    #[serde(deserialize_with = "int_or_string")]
+    pub dmid: i64,
+
+    /// 评分，和弹幕权重一样为 0 到 10
+    #[prost(uint32, tag = "2")]
+    pub flag: u32,
+}
+
+#[derive(Clone, Message, Deserialize)]
+pub struct DanmakuAIFlag {
+    #[prost(message, repeated, tag = "1")]
+    #[serde(default, alias = "dmFlags")]
+    pub dm_flags: Vec<DanmakuFlag>,
+}
+
+#[derive(Clone, Message, Deserialize)]
 pub struct DmSegMobileReply {
     #[prost(message, repeated, tag = "1")]
     pub elems: Vec<DanmakuElem>,
+
+    #[prost(message, optional, tag = "3")]
+    #[serde(default, alias = "aiFlag")]
+    pub ai_flag: Option<DanmakuAIFlag>,
+}
+
+impl DmSegMobileReply {
+    /// 弹幕列表，有 AI 评分的弹幕使用评分作为权重
+    pub fn into_elems(self) -> Vec<DanmakuElem> {
+        let mut elems = self.elems;
+        let flags: std::collections::HashMap<i64, u32> = self
+            .ai_flag
+            .into_iter()
+            .flat_map(|f| f.dm_flags)
+            .map(|f| (f.dmid, f.flag))
+            .collect();
+        if !flags.is_empty() {
+            for elem in elems.iter_mut() {
+                if let Some(flag) = flags.get(&elem.id) {
+                    elem.weight = *flag as i32;
+                }
+            }
+        }
+        elems
+    }
+}
+
+/// 互动弹幕，如 UP 主弹幕、投票、关联视频、评分等
+#[derive(Clone, Message)]
+pub struct CommandDm {
+    #[prost(int64, tag = "1")]
+    pub id: i64,
+
+    /// 视频 cid
+    #[prost(int64, tag = "2")]
+    pub oid: i64,
+
+    /// 发送者 mid
+    #[prost(string, tag = "3")]
+    pub mid: String,
+
+    /// 互动弹幕指令，如 `#UP#`、`#VOTE#`、`#LINK#`
+    #[prost(string, tag = "4")]
+    pub command: String,
+
+    /// 互动弹幕正文
+    #[prost(string, tag = "5")]
+    pub content: String,
+
+    /// 出现时间（单位 ms）
+    #[prost(int32, tag = "6")]
+    pub progress: i32,
+
+    #[prost(string, tag = "7")]
+    pub ctime: String,
+
+    #[prost(string, tag = "8")]
+    pub mtime: String,
+
+    /// JSON 格式的扩展数据，如投票的选项
+    #[prost(string, tag = "9")]
+    pub extra: String,
+
+    #[prost(string, tag = "10")]
+    pub id_str: String,
+}
+
+/// 表情的生效时间段
+#[derive(Clone, Message)]
+pub struct Period {
+    #[prost(int64, tag = "1")]
+    pub start: i64,
+
+    #[prost(int64, tag = "2")]
+    pub end: i64,
+}
+
+/// 弹幕中的表情，如 `[doge]`
+#[derive(Clone, Message)]
+pub struct Expression {
+    #[prost(string, repeated, tag = "1")]
+    pub keyword: Vec<String>,
+
+    /// 表情图片的地址
+    #[prost(string, tag = "2")]
+    pub url: String,
+
+    #[prost(message, repeated, tag = "3")]
+    pub period: Vec<Period>,
+}
+
+#[derive(Clone, Message)]
+pub struct Expressions {
+    #[prost(message, repeated, tag = "1")]
+    pub data: Vec<Expression>,
+}
+
+/// web 端的弹幕元数据，只翻译了需要的字段
+///
+/// app 端的 `DmViewReply` 中没有互动弹幕，所以使用 web 端的 `DmWebViewReply`
+#[derive(Clone, Message)]
+pub struct DmWebViewReply {
+    /// 高级弹幕专包的地址
+    #[prost(string, repeated, tag = "6")]
+    pub special_dms: Vec<String>,
+
+    /// 弹幕数
+    #[prost(int64, tag = "8")]
+    pub count: i64,
+
+    #[prost(message, repeated, tag = "9")]
+    pub command_dms: Vec<CommandDm>,
+
+    #[prost(message, repeated, tag = "12")]
+    pub expressions: Vec<Expressions>,
+}
+
+fn no_weight() -> i32 {
+    -1
+}
+
+fn int_or_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum IntOrString<T> {
+        Int(T),
+        String(String),
+    }
+    match IntOrString::<T>::deserialize(deserializer)? {
+        IntOrString::Int(v) => Ok(v),
+        IntOrString::String(s) => s.parse().map_err(serde::de::Error::custom),
+    }
 }
 
 impl From<DanmakuElem> for crate::Danmu {
@@ -78,6 +249,66 @@ impl From<DanmakuElem> for crate::Danmu {
                 ((elem.color >> 8) & 0xFF) as u8,
                 (elem.color & 0xFF) as u8,
             ),
+            sender: Some(elem.mid_hash).filter(|s| !s.is_empty()),
+            // 权重 0 也是有效的权重，会被智能屏蔽；没有权重的弹幕不参与智能屏蔽
+            weight: u32::try_from(elem.weight).ok(),
+            pool: crate::danmu::Pool::from_num(elem.pool.max(0) as u32),
+            font_scale: None,
+        }
+    }
+}
+
+/// 互动弹幕没有指定显示时间时的默认值，单位为毫秒
+const COMMAND_DURATION_MS: u32 = 5000;
+
+impl From<CommandDm> for crate::Danmu {
+    /// 转换为卡片，标题和内容按指令区分，不认识的指令直接显示正文
+    fn from(dm: CommandDm) -> Self {
+        let extra: serde_json::Value = serde_json::from_str(&dm.extra).unwrap_or_default();
+        let or_content = |key: &str| {
+            extra[key]
+                .as_str()
+                .filter(|s| !s.is_empty())
+                .unwrap_or(&dm.content)
+                .to_string()
+        };
+        let (title, content) = match dm.command.as_str() {
+            "#UP#" => ("UP 主", dm.content.clone()),
+            "#VOTE#" => {
+                let mut lines = vec![or_content("question")];
+                if let Some(options) = extra["options"].as_array() {
+                    lines.extend(options.iter().map(|o| {
+                        format!(
+                            "{}. {}",
+                            o["idx"].as_u64().unwrap_or_default(),
+                            o["desc"].as_str().unwrap_or_default()
+                        )
+                    }));
+                }
+                ("投票", lines.join("\n"))
+            }
+            "#LINK#" => ("相关视频", or_content("title")),
+            "#GRADE#" => ("评分", or_content("msg")),
+            "#RESERVE#" => ("预约", or_content("title")),
+            "#ATTENTION#" | "#ACTORFOLLOW#" | "#MANAGERFOLLOW#" => ("关注", dm.content.clone()),
+            _ => ("互动弹幕", dm.content.clone()),
+        };
+        Self {
+            timeline_s: dm.progress as f64 / 1000.0,
+            content,
+            r#type: crate::danmu::DanmuType::Command {
+                title: title.to_string(),
+                duration_ms: extra["duration"]
+                    .as_u64()
+                    .filter(|d| *d > 0)
+                    .map_or(COMMAND_DURATION_MS, |d| d as u32),
+            },
+            fontsize: 25,
+            rgb: (0xFF, 0xFF, 0xFF),
+            sender: Some(dm.mid).filter(|s| !s.is_empty()),
+            weight: None,
+            pool: crate::danmu::Pool::Normal,
+            font_scale: None,
         }
     }
 }
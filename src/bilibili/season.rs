@@ -36,16 +36,28 @@ pub struct Episode {
     #[serde(rename = "duration")]
     pub duration_ms: u64,
 
+    /// 集数，如 `1`
     pub title: String,
+
+    /// 单集标题
+    #[serde(default)]
+    pub long_title: String,
+}
+
+impl Episode {
+    /// 单集标题，没有时使用集数
+    pub fn display_title(&self) -> &str {
+        if self.long_title.is_empty() {
+            &self.title
+        } else {
+            &self.long_title
+        }
+    }
 }
 
 impl Season {
-    pub async fn request(client: &reqwest::Client, args: (&'static str, u64)) -> Result<Self> {
-        let request = client
-            .get("https://api.bilibili.com/pgc/view/web/season")
-            .query(&[args])
-            .send();
-        let response = request.await?;
+    pub async fn request(client: &super::Client, args: (&'static str, u64)) -> Result<Self> {
+        let response = client.get("/pgc/view/web/season", &[args]).await?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -0,0 +1,62 @@
+//! b23.tv 短链接
+use super::Client;
+use anyhow::{Context, Result};
+use url::Url;
+
+/// 请求短链接，返回跳转的目标地址。超时、重试和限速与其他请求相同
+pub async fn resolve_short_link(client: &Client, url: &Url) -> Result<Url> {
+    let resp = client.get_no_redirect(url).await?;
+    anyhow::ensure!(
+        resp.status().is_redirection(),
+        "短链接 {} 没有跳转，status = {}",
+        url,
+        resp.status()
+    );
+    let location = resp
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .context("短链接的跳转没有 Location")?
+        .to_str()
+        .context("短链接的跳转地址不合法")?;
+    url.join(location).context("短链接的跳转地址不合法")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bilibili::client::tests::{
+        client, mock_server, mock_server_with_headers, MockResponse,
+    };
+
+    #[tokio::test]
+    async fn resolve() {
+        let base_url = mock_server_with_headers(|path, i| {
+            assert_eq!(path, "/abcdEFG");
+            // 风控时和其他请求一样重试
+            if i == 0 {
+                return (412, vec![]).into();
+            }
+            MockResponse {
+                status: 302,
+                headers: vec![(
+                    "Location",
+                    "https://m.bilibili.com/video/BV1z44y1E7m6?p=2&share_source=copy_web"
+                        .to_string(),
+                )],
+                body: vec![],
+            }
+        });
+        let url = Url::parse(&format!("{base_url}/abcdEFG")).unwrap();
+        assert_eq!(
+            resolve_short_link(&client(base_url), &url)
+                .await
+                .unwrap()
+                .as_str(),
+            "https://m.bilibili.com/video/BV1z44y1E7m6?p=2&share_source=copy_web"
+        );
+
+        let base_url = mock_server(|_, _| (404, vec![]));
+        let url = Url::parse(&format!("{base_url}/abcdEFG")).unwrap();
+        assert!(resolve_short_link(&client(base_url), &url).await.is_err());
+    }
+}
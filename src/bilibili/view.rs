@@ -0,0 +1,88 @@
+//! 弹幕元数据，包含互动弹幕和表情
+use super::{Client, CommandDm, DmWebViewReply, Expression};
+use anyhow::{Context, Result};
+use prost::Message;
+
+const PATH: &str = "/x/v2/dm/web/view";
+
+/// 视频的互动弹幕和表情
+#[derive(Debug, Default)]
+pub struct DmView {
+    pub commands: Vec<CommandDm>,
+    pub expressions: Vec<Expression>,
+}
+
+pub async fn get_dm_view(client: &Client, aid: u64, cid: u64) -> Result<DmView> {
+    let resp = client
+        .get(PATH, &[("type", 1), ("oid", cid), ("pid", aid)])
+        .await?;
+    let is_json_resp = resp
+        .headers()
+        .get("content-type")
+        .map(|v| v.as_bytes().starts_with(b"application/json"))
+        .unwrap_or(false);
+    if is_json_resp {
+        biliapi::requests::BiliResponse::<()>::from_response(resp).await?;
+        anyhow::bail!("The response should fail");
+    }
+    let content = resp.bytes().await?;
+    let reply = DmWebViewReply::decode(content).context("弹幕元数据无法解析为 PB")?;
+    let mut commands = reply.command_dms;
+    commands.sort_unstable_by_key(|d| d.progress);
+    debug!(
+        "视频 cid={} 有 {} 条互动弹幕，{} 组表情",
+        cid,
+        commands.len(),
+        reply.expressions.len()
+    );
+    Ok(DmView {
+        commands,
+        expressions: reply.expressions.into_iter().flat_map(|e| e.data).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bilibili::client::tests::{client, mock_server};
+    use crate::bilibili::Expressions;
+
+    #[tokio::test]
+    async fn view() {
+        let base_url = mock_server(|path, _| {
+            assert!(path.starts_with("/x/v2/dm/web/view?type=1&oid=2&pid=1"));
+            let reply = DmWebViewReply {
+                command_dms: vec![
+                    CommandDm {
+                        command: "#LINK#".to_string(),
+                        progress: 20_000,
+                        ..Default::default()
+                    },
+                    CommandDm {
+                        command: "#VOTE#".to_string(),
+                        progress: 10_000,
+                        ..Default::default()
+                    },
+                ],
+                expressions: vec![Expressions {
+                    data: vec![Expression {
+                        keyword: vec!["[doge]".to_string()],
+                        url: "https://i0.hdslb.com/doge.png".to_string(),
+                        period: vec![],
+                    }],
+                }],
+                ..Default::default()
+            };
+            (200, reply.encode_to_vec())
+        });
+        let view = get_dm_view(&client(base_url), 1, 2).await.unwrap();
+        assert_eq!(
+            view.commands
+                .iter()
+                .map(|d| d.command.as_str())
+                .collect::<Vec<_>>(),
+            vec!["#VOTE#", "#LINK#"]
+        );
+        assert_eq!(view.expressions[0].keyword, vec!["[doge]"]);
+    }
+}
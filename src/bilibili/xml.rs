@@ -0,0 +1,96 @@
+//! 将下载的弹幕保存为哔哩哔哩格式的 XML，方便存档和之后重新转换
+use super::DanmakuElem;
+use anyhow::Result;
+use std::io::Write;
+
+/// 写入 `<i><d p="...">` 格式的 XML，p 属性与哔哩哔哩一致：
+///
+/// 时间（秒）, 弹幕类型, 字号, 颜色, 发送时间戳, 弹幕池, 发送者 mid_hash, dmid, 权重
+pub fn write_xml<W: Write>(mut w: W, elems: &[DanmakuElem]) -> Result<()> {
+    write!(
+        w,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <i>\n\
+        <chatserver>chat.bilibili.com</chatserver>\n\
+        <maxlimit>{}</maxlimit>\n\
+        <source>danmu2ass</source>\n",
+        elems.len()
+    )?;
+    for elem in elems {
+        let dmid = if elem.dmid_str.is_empty() {
+            elem.id.to_string()
+        } else {
+            elem.dmid_str.clone()
+        };
+        writeln!(
+            w,
+            "<d p=\"{:.5},{},{},{},{},{},{},{},{}\">{}</d>",
+            elem.progress as f64 / 1000.0,
+            elem.mode,
+            elem.fontsize,
+            elem.color,
+            elem.ctime,
+            elem.pool,
+            escape(&elem.mid_hash),
+            escape(&dmid),
+            elem.weight,
+            escape(&elem.content),
+        )?;
+    }
+    writeln!(w, "</i>")?;
+    w.flush()?;
+    Ok(())
+}
+
+/// XML 转义，并去掉 XML 中不允许出现的控制字符
+pub(crate) fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' | '\n' | '\r' => out.push(ch),
+            c if (c as u32) < 0x20 => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let elems = vec![DanmakuElem {
+            id: 1234567890123,
+            progress: 12345,
+            mode: 5,
+            fontsize: 25,
+            color: 0xFF0000,
+            mid_hash: "1537d7c7".to_string(),
+            content: "<a & \"b\">\u{8}".to_string(),
+            ctime: 1647777083,
+            pool: 1,
+            ..Default::default()
+        }];
+        let mut xml = vec![];
+        write_xml(&mut xml, &elems).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains(
+            r#"<d p="12.34500,5,25,16711680,1647777083,1,1537d7c7,1234567890123,0">&lt;a &amp; &quot;b&quot;&gt;</d>"#
+        ));
+
+        let danmus = crate::Parser::new(xml.as_bytes())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(danmus.len(), 1);
+        assert_eq!(danmus[0].timeline_s, 12.345);
+        assert_eq!(danmus[0].content, r#"0-<a & "b">"#);
+        assert_eq!(danmus[0].rgb, (0xFF, 0, 0));
+        assert_eq!(danmus[0].sender.as_deref(), Some("1537d7c7"));
+    }
+}
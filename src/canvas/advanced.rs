@@ -0,0 +1,232 @@
+//! 高级弹幕（mode 7），内容是一个 JSON 数组，指定了位置、透明度、旋转和移动
+//!
+//! `[x, y, "透明度", 持续时间, 文字, Z 轴旋转, Y 轴旋转, 终点 x, 终点 y, 移动时间, 移动延迟, 描边, 字体, 线性加速, 运动路径]`
+//! 除了前五项之外都可以省略
+use super::Config;
+use crate::{Danmu, DrawEffect, Drawable};
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// 哔哩哔哩播放器的舞台大小，像素坐标以此为准
+const STAGE_SIZE: (f64, f64) = (672.0, 438.0);
+
+/// 最短的显示时间，单位为秒，持续时间为 0 或负数的高级弹幕按这个时间显示
+const MIN_DURATION: f64 = 0.1;
+
+/// 解析之后的高级弹幕，坐标为舞台上的像素
+#[derive(Debug, Clone, PartialEq)]
+pub struct Advanced {
+    pub start: (f64, f64),
+    pub end: (f64, f64),
+    /// 开始和结束时的不透明度，0 为透明，1 为不透明
+    pub alpha: (f64, f64),
+    /// 单位为秒
+    pub duration: f64,
+    pub text: String,
+    /// 哔哩哔哩的旋转角度，单位为度，Z 轴为顺时针
+    pub rotate_z: f64,
+    pub rotate_y: f64,
+    /// 移动的开始和结束时间，相对于弹幕出现的时间，单位为毫秒
+    pub move_time: (u32, u32),
+    pub stroke: bool,
+    pub font: Option<String>,
+}
+
+/// 坐标：带小数点的 0 到 1 之间的数为舞台大小的比例，否则为像素
+fn position(v: &Value, stage: f64) -> Option<f64> {
+    let (value, is_ratio) = match v {
+        Value::String(s) => (s.trim().parse::<f64>().ok()?, s.contains('.')),
+        Value::Number(n) => (n.as_f64()?, n.is_f64()),
+        _ => return None,
+    };
+    Some(if is_ratio && (0.0..=1.0).contains(&value) {
+        value * stage
+    } else {
+        value
+    })
+}
+
+fn number(v: &Value) -> Option<f64> {
+    match v {
+        Value::String(s) => s.trim().parse().ok(),
+        Value::Number(n) => n.as_f64(),
+        _ => None,
+    }
+}
+
+/// 0、false 以及它们的字符串形式为 false
+fn boolean(v: &Value) -> Option<bool> {
+    match v {
+        Value::Bool(b) => Some(*b),
+        Value::String(s) if s.trim() == "true" => Some(true),
+        Value::String(s) if s.trim() == "false" => Some(false),
+        _ => number(v).map(|n| n != 0.0),
+    }
+}
+
+/// 运动路径 `M12,34L56,78...`，只使用起点和终点
+fn path_endpoints(path: &str) -> Option<((f64, f64), (f64, f64))> {
+    let points: Vec<(f64, f64)> = path
+        .split(['M', 'L'])
+        .filter_map(|p| {
+            let (x, y) = p.trim().split_once(',')?;
+            Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+        })
+        .collect();
+    Some((*points.first()?, *points.last()?))
+}
+
+impl Advanced {
+    pub fn parse(content: &str) -> Result<Self> {
+        // debug 构建中 content 前面带有序号
+        let json = &content[content.find('[').context("高级弹幕不是 JSON 数组")?..];
+        let items: Vec<Value> = serde_json::from_str(json).context("高级弹幕不是 JSON 数组")?;
+        let get = |i: usize| items.get(i).filter(|v| !v.is_null());
+
+        let start = (
+            get(0)
+                .and_then(|v| position(v, STAGE_SIZE.0))
+                .context("高级弹幕中没有 x")?,
+            get(1)
+                .and_then(|v| position(v, STAGE_SIZE.1))
+                .context("高级弹幕中没有 y")?,
+        );
+        let alpha: (f64, f64) = match get(2).and_then(Value::as_str).map(|s| s.split_once('-')) {
+            Some(Some((a, b))) => (
+                a.trim().parse().context("透明度解析错误")?,
+                b.trim().parse().context("透明度解析错误")?,
+            ),
+            _ => (1.0, 1.0),
+        };
+        let duration = get(3).and_then(number).unwrap_or(4.5).max(MIN_DURATION);
+        let text = get(4)
+            .and_then(Value::as_str)
+            .context("高级弹幕中没有文字")?
+            // 哔哩哔哩用 /n 表示换行
+            .replace("/n", "\n");
+        let end = (
+            get(7)
+                .and_then(|v| position(v, STAGE_SIZE.0))
+                .unwrap_or(start.0),
+            get(8)
+                .and_then(|v| position(v, STAGE_SIZE.1))
+                .unwrap_or(start.1),
+        );
+        let move_duration = get(9).and_then(number).unwrap_or(duration * 1000.0);
+        let delay = get(10).and_then(number).unwrap_or(0.0);
+        let (start, end) = match get(14).and_then(Value::as_str).and_then(path_endpoints) {
+            Some(endpoints) => endpoints,
+            None => (start, end),
+        };
+
+        Ok(Self {
+            start,
+            end,
+            alpha: (alpha.0.clamp(0.0, 1.0), alpha.1.clamp(0.0, 1.0)),
+            duration,
+            text,
+            rotate_z: get(5).and_then(number).unwrap_or(0.0),
+            rotate_y: get(6).and_then(number).unwrap_or(0.0),
+            move_time: (
+                delay.max(0.0) as u32,
+                (delay + move_duration).max(0.0) as u32,
+            ),
+            stroke: get(11).and_then(boolean).unwrap_or(true),
+            font: get(12)
+                .and_then(Value::as_str)
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty()),
+        })
+    }
+}
+
+/// 按舞台和画布的比例缩放，保持宽高比，多出来的部分居中
+pub fn draw(mut danmu: Danmu, config: &Config) -> Option<Drawable> {
+    let advanced = match Advanced::parse(&danmu.content) {
+        Ok(advanced) => advanced,
+        Err(e) => {
+            debug!("高级弹幕 {} 解析失败：{:?}", danmu.content, e);
+            return None;
+        }
+    };
+    let zoom = (config.width as f64 / STAGE_SIZE.0).min(config.height as f64 / STAGE_SIZE.1);
+    let offset = (
+        (config.width as f64 - STAGE_SIZE.0 * zoom) / 2.0,
+        (config.height as f64 - STAGE_SIZE.1 * zoom) / 2.0,
+    );
+    let map = |(x, y): (f64, f64)| {
+        (
+            (x * zoom + offset.0).round() as i32,
+            (y * zoom + offset.1).round() as i32,
+        )
+    };
+
+    let fontsize = if danmu.fontsize == 0 {
+        25
+    } else {
+        danmu.fontsize
+    };
+    danmu.font_scale = Some(fontsize as f64 * zoom / config.font_size as f64);
+    danmu.content = advanced.text;
+    Some(Drawable::new(
+        danmu,
+        advanced.duration,
+        "Advanced",
+        DrawEffect::Advanced {
+            start: map(advanced.start),
+            end: map(advanced.end),
+            move_time: advanced.move_time,
+            alpha: advanced.alpha,
+            rotate: (advanced.rotate_z, advanced.rotate_y),
+            stroke: advanced.stroke,
+            font: advanced.font,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let advanced = Advanced::parse(
+            r#"["0.5","0.25","1-0",3,"第一行/n第二行",30,60,"0.25",100,500,1000,0,"微软雅黑",1,"M336,100L100,200L200,300"]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            advanced,
+            Advanced {
+                start: (336.0, 100.0),
+                end: (200.0, 300.0),
+                alpha: (1.0, 0.0),
+                duration: 3.0,
+                text: "第一行\n第二行".to_string(),
+                rotate_z: 30.0,
+                rotate_y: 60.0,
+                move_time: (1000, 1500),
+                stroke: false,
+                font: Some("微软雅黑".to_string()),
+            }
+        );
+
+        // 只有前五项，整数坐标为像素
+        let advanced = Advanced::parse(r#"0-[10,20,"0.8-0.8",4.5,"文字"]"#).unwrap();
+        assert_eq!(advanced.start, (10.0, 20.0));
+        assert_eq!(advanced.end, (10.0, 20.0));
+        assert_eq!(advanced.alpha, (0.8, 0.8));
+        assert_eq!(advanced.move_time, (0, 4500));
+        assert!(advanced.stroke);
+        assert_eq!(advanced.font, None);
+
+        // 持续时间为 0 或负数时使用最短显示时间
+        let advanced = Advanced::parse(r#"[10,20,"1-1",0,"文字"]"#).unwrap();
+        assert_eq!(advanced.duration, MIN_DURATION);
+        assert_eq!(advanced.move_time, (0, 100));
+        let advanced = Advanced::parse(r#"[10,20,"1-1","-3","文字"]"#).unwrap();
+        assert_eq!(advanced.duration, MIN_DURATION);
+
+        assert!(Advanced::parse("普通弹幕").is_err());
+        assert!(Advanced::parse(r#"[0,0,"1-1",4.5]"#).is_err());
+    }
+}
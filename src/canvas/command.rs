@@ -0,0 +1,95 @@
+//! 互动弹幕（UP 主弹幕、投票、关联视频等）绘制为屏幕右上角的卡片
+use super::{superchat::wrap, Config};
+use crate::{danmu::DanmuType, Danmu, DrawEffect, Drawable};
+use float_ord::FloatOrd;
+
+/// 卡片的宽度占屏幕宽度的比例
+const WIDTH_RATIO: f64 = 0.3;
+/// 卡片区域最多占屏幕高度的比例
+const HEIGHT_RATIO: f64 = 0.5;
+const BACKGROUND: (u8, u8, u8) = (0x33, 0x33, 0x33);
+
+/// 卡片区域，从上往下按 lane 划分为槽位，一张卡片占据连续的若干个槽位
+#[derive(Debug, Clone)]
+pub struct CommandCards {
+    /// 每个槽位空出来的时间，下标 0 为最上方
+    slots: Vec<f64>,
+}
+
+impl CommandCards {
+    pub fn new(config: &Config) -> Self {
+        let cnt = (HEIGHT_RATIO * config.height as f64 / config.lane_size as f64) as usize;
+        Self {
+            slots: vec![0.0; cnt],
+        }
+    }
+
+    pub fn draw(&mut self, mut danmu: Danmu, config: &Config) -> Option<Drawable> {
+        let DanmuType::Command { title, duration_ms } = &danmu.r#type else {
+            return None;
+        };
+        if self.slots.is_empty() {
+            return None;
+        }
+        let duration = *duration_ms as f64 / 1000.0;
+        let lane_size = config.lane_size as i32;
+        let padding = lane_size / 4;
+        let width = (config.width as f64 * WIDTH_RATIO) as i32;
+
+        let max_lines =
+            ((self.slots.len() as i32 * lane_size - 2 * padding) / config.font_size as i32).max(2);
+        let mut lines = wrap(&danmu.content, (width - 2 * padding) as f64, config);
+        lines.retain(|l| !l.is_empty());
+        if lines.len() as i32 > max_lines - 1 {
+            lines.truncate((max_lines - 1) as usize);
+            if let Some(last) = lines.last_mut() {
+                last.pop();
+                last.push('…');
+            }
+        }
+        let text_height = (lines.len() as i32 + 1) * config.font_size as i32 + 2 * padding;
+        let k =
+            ((text_height + lane_size - 1) / lane_size).clamp(1, self.slots.len() as i32) as usize;
+
+        // 找到最早空出来的连续 k 个槽位，相同时优先上方
+        let (FloatOrd(free_at), p) = (0..=self.slots.len() - k)
+            .map(|p| {
+                let free_at = self.slots[p..p + k]
+                    .iter()
+                    .copied()
+                    .fold(f64::MIN, f64::max);
+                (FloatOrd(free_at), p)
+            })
+            .min()?;
+        if free_at > danmu.timeline_s {
+            let time_need = free_at - danmu.timeline_s;
+            // 和滚动弹幕一样只允许延迟 1s
+            if time_need >= 1.0 {
+                debug!("互动弹幕区域已满，跳过：{}", danmu.content);
+                return None;
+            }
+            debug!("互动弹幕区域已满，延迟 {} 秒", time_need);
+            danmu.timeline_s = free_at;
+        }
+        for slot in &mut self.slots[p..p + k] {
+            *slot = danmu.timeline_s + duration;
+        }
+
+        danmu.content = std::iter::once(title.as_str())
+            .chain(lines.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join("\n");
+        danmu.rgb = (0xFF, 0xFF, 0xFF);
+        Some(Drawable::new(
+            danmu,
+            duration,
+            "Command",
+            DrawEffect::Boxed {
+                pos: (config.width as i32 - width, p as i32 * lane_size),
+                size: (width, k as i32 * lane_size),
+                padding,
+                background: BACKGROUND,
+            },
+        ))
+    }
+}
@@ -0,0 +1,93 @@
+//! 礼物和上舰显示在屏幕右下角的固定行中，从最下面一行开始找空位
+//!
+//! 开启后底部弹幕和字幕会排在礼物区域的上方，不会互相遮挡
+use super::{lane::Lane, Config};
+use crate::{danmu::DanmuType, Danmu, DrawEffect, Drawable};
+use float_ord::FloatOrd;
+
+/// 舰长等级对应的名称和颜色
+fn guard_level(level: u32) -> (&'static str, (u8, u8, u8)) {
+    match level {
+        1 => ("总督", (0xFF, 0x5B, 0x5B)),
+        2 => ("提督", (0xC0, 0x7B, 0xFF)),
+        _ => ("舰长", (0x5B, 0xB8, 0xFF)),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GiftTicker {
+    /// 下标 0 为最下方
+    lanes: Vec<Option<Lane>>,
+}
+
+impl GiftTicker {
+    pub fn new(config: &Config) -> Self {
+        let cnt =
+            (config.gift_percentage * config.height as f64 / config.lane_size as f64) as usize;
+        Self {
+            lanes: vec![None; cnt],
+        }
+    }
+
+    /// 礼物区域的行数
+    pub fn lane_count(&self) -> usize {
+        self.lanes.len()
+    }
+
+    pub fn draw(&mut self, mut danmu: Danmu, config: &Config) -> Option<Drawable> {
+        let (content, rgb) = match &danmu.r#type {
+            DanmuType::Gift {
+                user,
+                gift_name,
+                count,
+            } => (
+                format!("{user} 投喂 {gift_name} ×{count}"),
+                (0xFF, 0xD7, 0x00),
+            ),
+            DanmuType::Guard { user, level, count } => {
+                let (name, rgb) = guard_level(*level);
+                match count {
+                    0 | 1 => (format!("{user} 开通了{name}"), rgb),
+                    _ => (format!("{user} 开通了{name} ×{count}"), rgb),
+                }
+            }
+            _ => return None,
+        };
+        danmu.content = content;
+        danmu.rgb = rgb;
+
+        let mut collisions = Vec::with_capacity(self.lanes.len());
+        for (idx, lane) in self.lanes.iter().enumerate() {
+            let time_needed = match lane {
+                None => 0.0,
+                Some(l) => l.fixed_time_needed(&danmu, config),
+            };
+            if time_needed <= 0.0 {
+                return Some(self.draw_in_lane(danmu, idx, config));
+            }
+            collisions.push((FloatOrd(time_needed), idx));
+        }
+        if let Some(&(FloatOrd(time_need), lane_idx)) = collisions.iter().min() {
+            if time_need < 1.0 {
+                danmu.timeline_s += time_need + 0.01;
+                return Some(self.draw_in_lane(danmu, lane_idx, config));
+            }
+        }
+        debug!("skipping gift: {}", danmu.content);
+        None
+    }
+
+    fn draw_in_lane(&mut self, danmu: Danmu, lane_idx: usize, config: &Config) -> Drawable {
+        self.lanes[lane_idx] = Some(Lane::draw_fixed(&danmu));
+        let lane_size = config.lane_size as i32;
+        // 右下对齐
+        let x = config.width as i32 - lane_size / 4;
+        let y = config.height as i32 - lane_idx as i32 * lane_size;
+        Drawable::new(
+            danmu,
+            config.fixed_duration,
+            "Gift",
+            DrawEffect::Fixed { pos: (x, y) },
+        )
+    }
+}
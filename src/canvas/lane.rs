@@ -32,6 +32,11 @@ impl Lane {
         }
     }
 
+    /// 固定弹幕的槽位还需要多久才能空出来，<= 0 代表已经可以绘制
+    pub fn fixed_time_needed(&self, other: &Danmu, config: &CanvasConfig) -> f64 {
+        self.last_shoot_time + config.fixed_duration - other.timeline_s
+    }
+
     /// 这个槽位是否可以发射另外一条弹幕，返回可能的情形
     pub fn available_for(&self, other: &Danmu, config: &super::Config) -> Collision {
         #[allow(non_snake_case)]
@@ -96,4 +101,9 @@ impl Lane {
             }
         }
     }
+
+    /// 最后一条滚动弹幕完全离开屏幕的时间，反方向的弹幕要等到这之后才能使用这一行
+    pub fn clear_at(&self, config: &CanvasConfig) -> f64 {
+        self.last_shoot_time + config.duration
+    }
 }
@@ -1,11 +1,23 @@
 //! 决定绘画策略
+mod advanced;
+mod command;
+mod gift;
 mod lane;
+mod superchat;
 
 use super::{Danmu, Drawable};
-use crate::{canvas::lane::Collision, DrawEffect};
+use crate::{
+    canvas::lane::Collision,
+    danmu::{DanmuType, Pool},
+    DrawEffect,
+};
 use anyhow::Result;
+use command::CommandCards;
 use float_ord::FloatOrd;
+use gift::GiftTicker;
 use lane::Lane;
+use std::sync::Arc;
+use superchat::SuperChatArea;
 
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct Config {
@@ -14,6 +26,9 @@ pub struct Config {
     pub height: u32,
     pub font: String,
     pub font_size: u32,
+    /// 从字体文件中读取的字形宽度，没有时使用估算
+    #[serde(skip)]
+    pub font_metrics: Option<Arc<crate::FontMetrics>>,
     pub width_ratio: f64,
     /// 两条弹幕之间最小的水平距离
     pub horizontal_gap: f64,
@@ -21,9 +36,60 @@ pub struct Config {
     pub lane_size: u32,
     /// 屏幕上滚动弹幕最多高度百分比
     pub float_percentage: f64,
+    /// 屏幕上顶部弹幕最多高度百分比
+    #[serde(default = "default_fixed_percentage")]
+    pub top_percentage: f64,
     /// 屏幕上底部弹幕最多高度百分比
-    #[serde(skip_deserializing)]
+    #[serde(default = "default_fixed_percentage")]
     pub bottom_percentage: f64,
+    /// 保留顶部、底部弹幕为固定弹幕，否则转为滚动弹幕
+    #[serde(default)]
+    pub keep_fixed: bool,
+    /// 顶部、底部弹幕在屏幕上的持续时间
+    #[serde(default = "default_fixed_duration")]
+    pub fixed_duration: f64,
+    /// 逆向弹幕的处理方式
+    #[serde(default)]
+    pub reverse_mode: ReverseMode,
+    /// 是否绘制醒目留言
+    #[serde(default)]
+    pub superchat: bool,
+    /// 屏幕左下角醒目留言区域最多高度百分比
+    #[serde(default = "default_superchat_percentage")]
+    pub superchat_percentage: f64,
+    /// 是否绘制互动弹幕的卡片
+    #[serde(default)]
+    pub command: bool,
+    /// 弹幕中表情的替换，None 为不替换
+    #[serde(skip)]
+    pub emotes: Option<Arc<crate::Emotes>>,
+    /// 是否绘制礼物和上舰
+    #[serde(default)]
+    pub gift: bool,
+    /// 屏幕右下角礼物区域最多高度百分比
+    #[serde(default = "default_fixed_percentage")]
+    pub gift_percentage: f64,
+    /// 合并同一用户连击礼物的时间窗口，单位为秒，0 代表不合并
+    #[serde(default = "default_gift_combo_window")]
+    pub gift_combo_window: f64,
+    /// 合并相同或相近弹幕的时间窗口，单位为秒，0 代表不合并
+    #[serde(default)]
+    pub dedup_window: f64,
+    /// 归一化之后的编辑距离相似度达到这个值的弹幕会被合并，1 代表只合并归一化之后相同的弹幕
+    #[serde(default = "default_dedup_similarity")]
+    pub dedup_similarity: f64,
+    /// 合并之后按数量放大字号
+    #[serde(default)]
+    pub dedup_scale: bool,
+    /// 包含的弹幕池
+    #[serde(default = "default_pools")]
+    pub pools: Vec<Pool>,
+    /// 智能屏蔽等级，屏蔽权重低于这个等级的弹幕，0 代表不屏蔽
+    #[serde(default)]
+    pub ai_level: u32,
+    /// 按弹幕原本的字号相对于 25 缩放，大字号的弹幕会占用多个槽位
+    #[serde(default)]
+    pub source_font_size: bool,
     /// 透明度
     #[serde(rename = "alpha", deserialize_with = "deserialize_alpha_to_opacity")]
     pub opacity: u8,
@@ -34,6 +100,40 @@ pub struct Config {
     /// 时间轴偏移
     pub time_offset: f64,
 }
+/// 哔哩哔哩弹幕的默认字号，小字号为 18，大字号为 36
+const DEFAULT_FONT_SIZE: u32 = 25;
+
+/// 逆向弹幕的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ReverseMode {
+    /// 保留为从左往右滚动的逆向弹幕
+    Keep,
+    /// 转为普通滚动弹幕
+    #[default]
+    Float,
+    /// 丢弃
+    Drop,
+}
+
+fn default_fixed_percentage() -> f64 {
+    0.3
+}
+fn default_fixed_duration() -> f64 {
+    5.0
+}
+fn default_superchat_percentage() -> f64 {
+    0.4
+}
+fn default_gift_combo_window() -> f64 {
+    5.0
+}
+fn default_dedup_similarity() -> f64 {
+    0.8
+}
+fn default_pools() -> Vec<Pool> {
+    vec![Pool::Normal, Pool::Subtitle, Pool::Special]
+}
 fn deserialize_alpha_to_opacity<'de, D>(deserializer: D) -> Result<u8, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -47,15 +147,36 @@ where
 }
 
 impl Config {
+    /// 弹幕是否通过智能屏蔽和弹幕池的过滤，没有权重的弹幕不受智能屏蔽影响
+    pub fn accepts(&self, danmu: &Danmu) -> bool {
+        danmu.weight.is_none_or(|w| w >= self.ai_level) && self.pools.contains(&danmu.pool)
+    }
+
     pub fn canvas(self) -> Canvas {
         let float_lanes_cnt =
             (self.float_percentage * self.height as f64 / self.lane_size as f64) as usize;
+        let top_lanes_cnt =
+            (self.top_percentage * self.height as f64 / self.lane_size as f64) as usize;
         let bottom_lanes_cnt =
             (self.bottom_percentage * self.height as f64 / self.lane_size as f64) as usize;
 
+        let gift_ticker = GiftTicker::new(&self);
+        // 礼物区域占用右下角，底部弹幕和字幕排在礼物区域上方
+        let bottom_offset = if self.gift {
+            gift_ticker.lane_count()
+        } else {
+            0
+        };
+
         Canvas {
+            superchat_area: SuperChatArea::new(&self),
+            command_cards: CommandCards::new(&self),
+            gift_ticker,
+            bottom_offset,
             config: self,
             float_lanes: vec![None; float_lanes_cnt],
+            reverse_lanes: vec![None; float_lanes_cnt],
+            top_lanes: vec![None; top_lanes_cnt],
             bottom_lanes: vec![None; bottom_lanes_cnt],
         }
     }
@@ -64,7 +185,16 @@ impl Config {
 pub struct Canvas {
     pub config: Config,
     pub float_lanes: Vec<Option<Lane>>,
+    /// 逆向弹幕和滚动弹幕共用屏幕区域，但是方向相反，不能共用槽位
+    pub reverse_lanes: Vec<Option<Lane>>,
+    pub top_lanes: Vec<Option<Lane>>,
+    /// 字幕弹幕和底部弹幕共用槽位，避免重叠
     pub bottom_lanes: Vec<Option<Lane>>,
+    superchat_area: SuperChatArea,
+    command_cards: CommandCards,
+    gift_ticker: GiftTicker,
+    /// 底部弹幕让出的礼物区域行数
+    bottom_offset: usize,
 }
 
 impl Canvas {
@@ -73,39 +203,109 @@ impl Canvas {
         if danmu.timeline_s < 0.0 {
             return Ok(None);
         }
+        if self.config.source_font_size
+            && danmu.fontsize != DEFAULT_FONT_SIZE
+            && danmu.fontsize != 0
+        {
+            let scale =
+                danmu.font_scale.unwrap_or(1.0) * danmu.fontsize as f64 / DEFAULT_FONT_SIZE as f64;
+            danmu.font_scale = Some(scale);
+        }
+        if let Some(emotes) = self.config.emotes.as_ref() {
+            // 高级弹幕的内容是 JSON，绘制时再解析
+            if danmu.r#type != DanmuType::Advanced {
+                if let Some(content) = emotes.replace(&danmu.content) {
+                    danmu.content = content;
+                }
+            }
+        }
+        let is_text = matches!(
+            danmu.r#type,
+            DanmuType::Float | DanmuType::Top | DanmuType::Bottom | DanmuType::Reverse
+        );
+        if danmu.pool == Pool::Subtitle && is_text {
+            return Ok(self.draw_fixed(danmu));
+        }
         match danmu.r#type {
-            crate::danmu::DanmuType::Float => Ok(self.draw_float(danmu)),
-            crate::danmu::DanmuType::Bottom
-            | crate::danmu::DanmuType::Top
-            | crate::danmu::DanmuType::Reverse => {
+            DanmuType::Float => Ok(self.draw_float(danmu)),
+            DanmuType::Bottom | DanmuType::Top if self.config.keep_fixed => {
+                Ok(self.draw_fixed(danmu))
+            }
+            DanmuType::Reverse => match self.config.reverse_mode {
+                ReverseMode::Keep => Ok(self.draw_float(danmu)),
+                ReverseMode::Drop => Ok(None),
+                ReverseMode::Float => {
+                    danmu.r#type = DanmuType::Float;
+                    Ok(self.draw_float(danmu))
+                }
+            },
+            DanmuType::Advanced => Ok(advanced::draw(danmu, &self.config)),
+            DanmuType::Command { .. } if self.config.command => {
+                Ok(self.command_cards.draw(danmu, &self.config))
+            }
+            DanmuType::Command { .. } => Ok(None),
+            DanmuType::SuperChat { .. } if self.config.superchat => {
+                Ok(self.superchat_area.draw(danmu, &self.config))
+            }
+            DanmuType::SuperChat { .. } => Ok(None),
+            DanmuType::Gift { .. } | DanmuType::Guard { .. } if self.config.gift => {
+                Ok(self.gift_ticker.draw(danmu, &self.config))
+            }
+            DanmuType::Gift { .. } | DanmuType::Guard { .. } => Ok(None),
+            DanmuType::Bottom | DanmuType::Top => {
                 // 不喜欢底部弹幕，直接转成 Float
                 // 这是 feature 不是 bug
-                danmu.r#type = crate::danmu::DanmuType::Float;
+                danmu.r#type = DanmuType::Float;
                 Ok(self.draw_float(danmu))
             }
         }
     }
 
+    /// 弹幕需要占用的槽位数量，放大的弹幕可能需要多个连续的槽位；没有缩放的弹幕总是占用一个槽位
+    fn lane_slots(&self, danmu: &Danmu) -> usize {
+        let Some(scale) = danmu.font_scale else {
+            return 1;
+        };
+        let height = self.config.font_size as f64 * scale;
+        // 减去一个小量，避免刚好占满一行时因为浮点误差多占一个槽位
+        ((height - 1e-6) / self.config.lane_size as f64)
+            .ceil()
+            .max(1.0) as usize
+    }
+
+    /// 绘制滚动弹幕，逆向弹幕使用自己的槽位，但是不能和正向弹幕同时出现在同一行
     fn draw_float(&mut self, mut danmu: Danmu) -> Option<Drawable> {
-        let mut collisions = Vec::with_capacity(self.float_lanes.len());
-        for (idx, lane) in self.float_lanes.iter_mut().enumerate() {
-            match lane {
-                // 优先画不存在的槽位
-                None => {
-                    return Some(self.draw_float_in_lane(danmu, idx));
+        let reverse = danmu.r#type == DanmuType::Reverse;
+        let (lanes, opposite) = if reverse {
+            (&self.reverse_lanes, &self.float_lanes)
+        } else {
+            (&self.float_lanes, &self.reverse_lanes)
+        };
+        let slots = self.lane_slots(&danmu);
+        let mut collisions = Vec::with_capacity(lanes.len());
+        for (idx, window) in lanes.windows(slots).enumerate() {
+            // 连续的几个槽位都可以绘制时才可以绘制，否则取需要等待最久的槽位
+            let mut time_needed: Option<f64> = None;
+            // 逆向弹幕的碰撞是正向弹幕的镜像，令 x' = W - x 之后计算方式相同
+            for lane in window.iter().flatten() {
+                if let Collision::Collide { time_needed: t } =
+                    lane.available_for(&danmu, &self.config)
+                {
+                    time_needed = Some(time_needed.map_or(t, |max| max.max(t)));
                 }
-                Some(l) => {
-                    let col = l.available_for(&danmu, &self.config);
-                    match col {
-                        Collision::Separate { .. } | Collision::NotEnoughTime { .. } => {
-                            return Some(self.draw_float_in_lane(danmu, idx));
-                        }
-                        Collision::Collide { time_needed } => {
-                            collisions.push((FloatOrd(time_needed), idx));
-                        }
-                    }
+            }
+            // 相向运动的弹幕一定会相遇，需要等这一行反方向的弹幕全部离开屏幕
+            for lane in opposite[idx..idx + slots].iter().flatten() {
+                let t = lane.clear_at(&self.config) - danmu.timeline_s;
+                if t > 0.0 {
+                    time_needed = Some(time_needed.map_or(t, |max| max.max(t)));
                 }
             }
+            match time_needed {
+                // 优先画不存在或者不会碰撞的槽位
+                None => return Some(self.draw_float_in_lane(danmu, idx)),
+                Some(time_needed) => collisions.push((FloatOrd(time_needed), idx)),
+            }
         }
         // 允许部分弹幕在延迟后填充
         if !collisions.is_empty() {
@@ -123,17 +323,492 @@ impl Canvas {
     }
 
     fn draw_float_in_lane(&mut self, danmu: Danmu, lane_idx: usize) -> Drawable {
-        self.float_lanes[lane_idx] = Some(Lane::draw(&danmu, &self.config));
+        let lane = Some(Lane::draw(&danmu, &self.config));
+        let slots = lane_idx..lane_idx + self.lane_slots(&danmu);
         let y = lane_idx as i32 * self.config.lane_size as i32;
         let l = danmu.length(&self.config);
+        let (start, end) = if danmu.r#type == DanmuType::Reverse {
+            self.reverse_lanes[slots].fill(lane);
+            ((-(l as i32), y), (self.config.width as i32, y))
+        } else {
+            self.float_lanes[slots].fill(lane);
+            ((self.config.width as i32, y), (-(l as i32), y))
+        };
         Drawable::new(
             danmu,
             self.config.duration,
             "Float",
-            DrawEffect::Move {
-                start: (self.config.width as i32, y),
-                end: (-(l as i32), y),
-            },
+            DrawEffect::Move { start, end },
         )
     }
+
+    /// 绘制顶部、底部弹幕以及字幕弹幕
+    fn draw_fixed(&mut self, mut danmu: Danmu) -> Option<Drawable> {
+        let lanes = match danmu.r#type {
+            _ if danmu.pool == Pool::Subtitle => &self.bottom_lanes,
+            DanmuType::Top => &self.top_lanes,
+            _ => &self.bottom_lanes,
+        };
+        let slots = self.lane_slots(&danmu);
+        let mut collisions = Vec::with_capacity(lanes.len());
+        for (idx, window) in lanes.windows(slots).enumerate() {
+            let time_needed = window
+                .iter()
+                .flatten()
+                .map(|l| l.fixed_time_needed(&danmu, &self.config))
+                .fold(0.0, f64::max);
+            if time_needed <= 0.0 {
+                return Some(self.draw_fixed_in_lane(danmu, idx));
+            }
+            collisions.push((FloatOrd(time_needed), idx));
+        }
+        // 和滚动弹幕一样，允许延迟 1s
+        if let Some(&(FloatOrd(time_need), lane_idx)) = collisions.iter().min() {
+            if time_need < 1.0 {
+                debug!("延迟固定弹幕 {} 秒", time_need);
+                danmu.timeline_s += time_need + 0.01;
+                return Some(self.draw_fixed_in_lane(danmu, lane_idx));
+            }
+        }
+        debug!("skipping fixed danmu: {}", danmu.content);
+        None
+    }
+
+    fn draw_fixed_in_lane(&mut self, danmu: Danmu, lane_idx: usize) -> Drawable {
+        let lane_size = self.config.lane_size as i32;
+        let slots = lane_idx..lane_idx + self.lane_slots(&danmu);
+        let x = self.config.width as i32 / 2;
+        // 顶部弹幕从上往下排，以上边缘定位；底部弹幕从下往上排，以下边缘定位
+        let bottom_y =
+            self.config.height as i32 - (self.bottom_offset + lane_idx) as i32 * lane_size;
+        let (lanes, y, style_name) = match danmu.r#type {
+            _ if danmu.pool == Pool::Subtitle => (&mut self.bottom_lanes, bottom_y, "Subtitle"),
+            DanmuType::Top => (&mut self.top_lanes, lane_idx as i32 * lane_size, "Top"),
+            _ => (&mut self.bottom_lanes, bottom_y, "Bottom"),
+        };
+        lanes[slots].fill(Some(Lane::draw_fixed(&danmu)));
+        Drawable::new(
+            danmu,
+            self.config.fixed_duration,
+            style_name,
+            DrawEffect::Fixed { pos: (x, y) },
+        )
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn config() -> Config {
+        Config {
+            duration: 15.0,
+            width: 1280,
+            height: 720,
+            font: "黑体".to_string(),
+            font_size: 25,
+            font_metrics: None,
+            width_ratio: 1.2,
+            horizontal_gap: 20.0,
+            lane_size: 32,
+            float_percentage: 0.5,
+            top_percentage: 0.3,
+            bottom_percentage: 0.3,
+            keep_fixed: true,
+            fixed_duration: 5.0,
+            reverse_mode: ReverseMode::Keep,
+            superchat: true,
+            superchat_percentage: 0.4,
+            command: true,
+            emotes: None,
+            gift: false,
+            gift_percentage: 0.3,
+            gift_combo_window: 5.0,
+            dedup_window: 0.0,
+            dedup_similarity: 0.8,
+            dedup_scale: false,
+            pools: default_pools(),
+            ai_level: 0,
+            source_font_size: false,
+            opacity: 0,
+            bold: false,
+            outline: 0.8,
+            time_offset: 0.0,
+        }
+    }
+
+    fn danmu(timeline_s: f64, r#type: DanmuType) -> Danmu {
+        Danmu {
+            timeline_s,
+            content: "弹幕".to_string(),
+            r#type,
+            fontsize: 25,
+            rgb: (255, 255, 255),
+            sender: None,
+            weight: None,
+            pool: Pool::Normal,
+            font_scale: None,
+        }
+    }
+
+    #[test]
+    fn ai_level() {
+        let config = Config {
+            ai_level: 5,
+            pools: vec![Pool::Normal],
+            ..config()
+        };
+        let weighted = |weight| Danmu {
+            weight,
+            ..danmu(0.0, DanmuType::Float)
+        };
+        assert!(!config.accepts(&weighted(Some(0))));
+        assert!(!config.accepts(&weighted(Some(4))));
+        assert!(config.accepts(&weighted(Some(5))));
+        assert!(config.accepts(&weighted(None)));
+        assert!(!config.accepts(&Danmu {
+            pool: Pool::Special,
+            ..weighted(Some(10))
+        }));
+
+        let config = Config {
+            ai_level: 0,
+            ..config
+        };
+        assert!(config.accepts(&weighted(Some(0))));
+    }
+
+    #[test]
+    fn fixed_danmu_use_own_lanes() {
+        let mut canvas = config().canvas();
+        let top = canvas.draw(danmu(0.0, DanmuType::Top)).unwrap().unwrap();
+        assert_eq!(top.style_name, "Top");
+        assert!(matches!(top.effect, DrawEffect::Fixed { pos: (640, 0) }));
+
+        // 第一条还在屏幕上，排到第二行
+        let top = canvas.draw(danmu(1.0, DanmuType::Top)).unwrap().unwrap();
+        assert!(matches!(top.effect, DrawEffect::Fixed { pos: (640, 32) }));
+
+        // 第一条已经消失，复用第一行
+        let top = canvas.draw(danmu(5.0, DanmuType::Top)).unwrap().unwrap();
+        assert!(matches!(top.effect, DrawEffect::Fixed { pos: (640, 0) }));
+
+        let bottom = canvas.draw(danmu(0.0, DanmuType::Bottom)).unwrap().unwrap();
+        assert_eq!(bottom.style_name, "Bottom");
+        assert!(matches!(
+            bottom.effect,
+            DrawEffect::Fixed { pos: (640, 720) }
+        ));
+    }
+
+    #[test]
+    fn fixed_danmu_flattened_by_default() {
+        let mut canvas = Config {
+            keep_fixed: false,
+            ..config()
+        }
+        .canvas();
+        let drawable = canvas.draw(danmu(0.0, DanmuType::Bottom)).unwrap().unwrap();
+        assert_eq!(drawable.style_name, "Float");
+        assert!(matches!(drawable.effect, DrawEffect::Move { .. }));
+    }
+
+    #[test]
+    fn reverse_danmu() {
+        let mut canvas = config().canvas();
+        let float = canvas.draw(danmu(0.0, DanmuType::Float)).unwrap().unwrap();
+        let reverse = canvas
+            .draw(danmu(0.0, DanmuType::Reverse))
+            .unwrap()
+            .unwrap();
+        let (DrawEffect::Move { start, end }, DrawEffect::Move { .. }) =
+            (&reverse.effect, &float.effect)
+        else {
+            panic!("滚动弹幕应该是 Move");
+        };
+        // 逆向弹幕从左往右，不和正向弹幕在同一行
+        assert_ne!(start.1, 0);
+        assert!(start.0 < 0);
+        assert_eq!(end.0, 1280);
+
+        // 同一时间在屏幕上的正向和逆向弹幕永远不在同一行
+        let mut canvas = config().canvas();
+        let mut rows: Vec<(bool, i32, f64)> = vec![];
+        for i in 0..200 {
+            let reverse = i % 3 == 0;
+            let r#type = if reverse {
+                DanmuType::Reverse
+            } else {
+                DanmuType::Float
+            };
+            if let Some(drawable) = canvas.draw(danmu(i as f64 * 0.2, r#type)).unwrap() {
+                let DrawEffect::Move { start, .. } = drawable.effect else {
+                    panic!("滚动弹幕应该是 Move");
+                };
+                let t = drawable.danmu.timeline_s;
+                assert!(!rows.iter().any(|&(r, y, t0)| {
+                    r != reverse && y == start.1 && t < t0 + drawable.duration
+                }));
+                rows.push((reverse, start.1, t));
+            }
+        }
+        assert!(rows.iter().any(|r| r.0) && rows.iter().any(|r| !r.0));
+
+        let mut canvas = Config {
+            reverse_mode: ReverseMode::Drop,
+            ..config()
+        }
+        .canvas();
+        assert!(canvas
+            .draw(danmu(0.0, DanmuType::Reverse))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn source_font_size() {
+        let mut canvas = Config {
+            source_font_size: true,
+            ..config()
+        }
+        .canvas();
+        let sized = |timeline_s, r#type, fontsize| Danmu {
+            fontsize,
+            ..danmu(timeline_s, r#type)
+        };
+        let y = |drawable: &Drawable| match drawable.effect {
+            DrawEffect::Move { start, .. } => start.1,
+            DrawEffect::Fixed { pos } => pos.1,
+            _ => unreachable!(),
+        };
+
+        // 大字号占用两行
+        let large = canvas
+            .draw(sized(0.0, DanmuType::Float, 36))
+            .unwrap()
+            .unwrap();
+        assert_eq!(large.danmu.font_scale, Some(1.44));
+        assert_eq!(y(&large), 0);
+        let normal = canvas
+            .draw(sized(0.0, DanmuType::Float, 25))
+            .unwrap()
+            .unwrap();
+        assert_eq!(normal.danmu.font_scale, None);
+        assert_eq!(y(&normal), 64);
+        // 小字号只占用一行
+        let small = canvas
+            .draw(sized(0.0, DanmuType::Float, 18))
+            .unwrap()
+            .unwrap();
+        assert_eq!(small.danmu.font_scale, Some(0.72));
+        assert_eq!(y(&small), 96);
+
+        let top = canvas
+            .draw(sized(0.0, DanmuType::Top, 36))
+            .unwrap()
+            .unwrap();
+        assert_eq!(y(&top), 0);
+        let top = canvas
+            .draw(sized(1.0, DanmuType::Top, 25))
+            .unwrap()
+            .unwrap();
+        assert_eq!(y(&top), 64);
+
+        // 默认不缩放
+        let mut canvas = config().canvas();
+        let large = canvas
+            .draw(sized(0.0, DanmuType::Float, 36))
+            .unwrap()
+            .unwrap();
+        assert_eq!(large.danmu.font_scale, None);
+        let normal = canvas
+            .draw(sized(0.0, DanmuType::Float, 25))
+            .unwrap()
+            .unwrap();
+        assert_eq!(y(&normal), 32);
+
+        // 字号大于行高时，没有缩放的弹幕也只占用一行
+        let mut canvas = Config {
+            font_size: 40,
+            ..config()
+        }
+        .canvas();
+        canvas.draw(danmu(0.0, DanmuType::Float)).unwrap().unwrap();
+        let second = canvas.draw(danmu(0.0, DanmuType::Float)).unwrap().unwrap();
+        assert_eq!(y(&second), 32);
+    }
+
+    #[test]
+    fn subtitle_pool() {
+        let mut canvas = config().canvas();
+        let subtitle = |r#type| Danmu {
+            pool: Pool::Subtitle,
+            ..danmu(1.0, r#type)
+        };
+        let first = canvas.draw(subtitle(DanmuType::Float)).unwrap().unwrap();
+        assert_eq!(first.style_name, "Subtitle");
+        assert!(matches!(
+            first.effect,
+            DrawEffect::Fixed { pos: (640, 720) }
+        ));
+        // 同时出现的字幕往上叠
+        let second = canvas.draw(subtitle(DanmuType::Top)).unwrap().unwrap();
+        assert_eq!(second.style_name, "Subtitle");
+        assert!(matches!(
+            second.effect,
+            DrawEffect::Fixed { pos: (640, 688) }
+        ));
+        // 底部弹幕排在字幕上方，不会重叠
+        let bottom = canvas.draw(danmu(1.0, DanmuType::Bottom)).unwrap().unwrap();
+        assert_eq!(bottom.style_name, "Bottom");
+        assert!(matches!(
+            bottom.effect,
+            DrawEffect::Fixed { pos: (640, 656) }
+        ));
+        // 反过来，字幕也不会盖住底部弹幕
+        let mut canvas = config().canvas();
+        let bottom = canvas.draw(danmu(1.0, DanmuType::Bottom)).unwrap().unwrap();
+        let subtitle = canvas.draw(subtitle(DanmuType::Float)).unwrap().unwrap();
+        assert_eq!(subtitle.style_name, "Subtitle");
+        let (DrawEffect::Fixed { pos: a }, DrawEffect::Fixed { pos: b }) =
+            (&bottom.effect, &subtitle.effect)
+        else {
+            panic!("应该是固定弹幕");
+        };
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn gift_ticker_below_bottom_lanes() {
+        let gift = Danmu {
+            r#type: DanmuType::Gift {
+                user: "用户".to_string(),
+                gift_name: "小心心".to_string(),
+                count: 1,
+            },
+            ..Default::default()
+        };
+        let mut canvas = Config {
+            gift: true,
+            ..config()
+        }
+        .canvas();
+        let ticker = canvas.draw(gift).unwrap().unwrap();
+        assert_eq!(ticker.style_name, "Gift");
+        assert!(matches!(
+            ticker.effect,
+            DrawEffect::Fixed { pos: (1272, 720) }
+        ));
+        // 礼物区域有 6 行，底部弹幕排在它的上方
+        let bottom = canvas.draw(danmu(0.0, DanmuType::Bottom)).unwrap().unwrap();
+        assert!(matches!(
+            bottom.effect,
+            DrawEffect::Fixed { pos: (640, 528) }
+        ));
+    }
+
+    #[test]
+    fn command_card() {
+        let mut canvas = config().canvas();
+        let vote = crate::bilibili::CommandDm {
+            command: "#VOTE#".to_string(),
+            progress: 3000,
+            extra: r#"{"question":"选哪个","duration":8000,"options":[{"idx":1,"desc":"A"},{"idx":2,"desc":"B"}]}"#.to_string(),
+            ..Default::default()
+        };
+        let card = canvas.draw(vote.clone().into()).unwrap().unwrap();
+        assert_eq!(card.style_name, "Command");
+        assert_eq!(card.danmu.timeline_s, 3.0);
+        assert_eq!(card.duration, 8.0);
+        assert_eq!(card.danmu.content, "投票\n选哪个\n1. A\n2. B");
+        let DrawEffect::Boxed { pos, size, .. } = card.effect else {
+            panic!("互动弹幕应该是 Boxed");
+        };
+        // 贴着右上角
+        assert_eq!((pos.0 + size.0, pos.1), (1280, 0));
+
+        // 区域满了之后不会无限往后排
+        let cards: Vec<_> = (0..20)
+            .map(|_| canvas.draw(vote.clone().into()).unwrap())
+            .collect();
+        assert!(cards.iter().any(Option::is_none));
+        assert!(cards.iter().flatten().all(|c| c.danmu.timeline_s == 3.0));
+    }
+
+    #[test]
+    fn emotes() {
+        let mut config = config();
+        config.emotes = Some(Arc::new(crate::Emotes::default()));
+        let mut canvas = config.canvas();
+        let danmu = Danmu {
+            content: "[doge][doge]".to_string(),
+            ..Default::default()
+        };
+        let drawable = canvas.draw(danmu).unwrap().unwrap();
+        assert_eq!(drawable.danmu.content, "🐶🐶");
+    }
+
+    #[test]
+    fn superchat() {
+        let mut canvas = config().canvas();
+        let sc = |timeline_s, price| Danmu {
+            timeline_s,
+            content: "醒目留言".to_string(),
+            r#type: DanmuType::SuperChat {
+                user: "用户".to_string(),
+                price,
+            },
+            ..Default::default()
+        };
+        let first = canvas.draw(sc(0.0, 30)).unwrap().unwrap();
+        assert_eq!(first.style_name, "SuperChat");
+        assert_eq!(first.duration, 10.0);
+        assert_eq!(first.danmu.content, "用户 ￥30\n醒目留言");
+        let DrawEffect::Boxed { pos, size, .. } = first.effect else {
+            panic!("醒目留言应该是 Boxed");
+        };
+        // 贴着左下角
+        assert_eq!(pos.0, 0);
+        assert_eq!(pos.1 + size.1, 720);
+
+        // 第二条叠在第一条上面
+        let second = canvas.draw(sc(1.0, 1000)).unwrap().unwrap();
+        assert_eq!(second.duration, 45.0);
+        let DrawEffect::Boxed {
+            pos: pos2,
+            size: size2,
+            ..
+        } = second.effect
+        else {
+            panic!("醒目留言应该是 Boxed");
+        };
+        assert_eq!(pos2.1 + size2.1, pos.1);
+    }
+
+    #[test]
+    fn superchat_overflow() {
+        let mut canvas = config().canvas();
+        let sc = |timeline_s| Danmu {
+            timeline_s,
+            content: "醒目留言".to_string(),
+            r#type: DanmuType::SuperChat {
+                user: "用户".to_string(),
+                price: 30,
+            },
+            ..Default::default()
+        };
+        // 9 个槽位，每条占 3 个，同时只能放下 3 条
+        let drawn = (0..10)
+            .filter_map(|_| canvas.draw(sc(0.0)).unwrap())
+            .count();
+        assert_eq!(drawn, 3);
+
+        // 快要空出来时允许少量延迟
+        let delayed = canvas.draw(sc(9.5)).unwrap().unwrap();
+        assert_eq!(delayed.danmu.timeline_s, 10.0);
+        assert!(canvas.draw(sc(10.0)).unwrap().is_some());
+        assert!(canvas.draw(sc(10.0)).unwrap().is_some());
+        // 延迟太久的直接跳过，不会无限往后排
+        assert!(canvas.draw(sc(10.0)).unwrap().is_none());
+    }
 }
@@ -0,0 +1,140 @@
+//! 醒目留言（SuperChat）固定在屏幕左下角的预留区域中，带有按价格着色的背景
+use super::Config;
+use crate::{
+    danmu::{text_length, DanmuType},
+    Danmu, DrawEffect, Drawable,
+};
+use float_ord::FloatOrd;
+
+/// 醒目留言的宽度占屏幕宽度的比例
+const WIDTH_RATIO: f64 = 0.3;
+
+/// 价格档位：(最低价格, 背景颜色, 显示时间)，颜色和哔哩哔哩直播间一致
+const PRICE_TIERS: &[(u32, (u8, u8, u8), f64)] = &[
+    (2000, (0xAB, 0x1A, 0x32), 60.0),
+    (1000, (0xE5, 0x4D, 0x4D), 45.0),
+    (500, (0xE0, 0x94, 0x43), 30.0),
+    (100, (0xE2, 0xB5, 0x2B), 20.0),
+    (50, (0x42, 0x7D, 0x9E), 15.0),
+    (0, (0x2A, 0x60, 0xB2), 10.0),
+];
+
+/// 返回价格对应的背景颜色和显示时间
+pub fn price_tier(price: u32) -> ((u8, u8, u8), f64) {
+    let (_, color, duration) = PRICE_TIERS
+        .iter()
+        .find(|(min_price, _, _)| price >= *min_price)
+        .unwrap_or(&PRICE_TIERS[PRICE_TIERS.len() - 1]);
+    (*color, *duration)
+}
+
+/// 醒目留言区域，从下往上按 lane 划分为槽位，一条留言占据连续的若干个槽位
+#[derive(Debug, Clone)]
+pub struct SuperChatArea {
+    /// 每个槽位空出来的时间，下标 0 为最下方
+    slots: Vec<f64>,
+}
+
+impl SuperChatArea {
+    pub fn new(config: &Config) -> Self {
+        let cnt =
+            (config.superchat_percentage * config.height as f64 / config.lane_size as f64) as usize;
+        Self {
+            slots: vec![0.0; cnt],
+        }
+    }
+
+    pub fn draw(&mut self, mut danmu: Danmu, config: &Config) -> Option<Drawable> {
+        let DanmuType::SuperChat { user, price } = &danmu.r#type else {
+            return None;
+        };
+        if self.slots.is_empty() {
+            return None;
+        }
+        let (background, duration) = price_tier(*price);
+        let lane_size = config.lane_size as i32;
+        let padding = lane_size / 4;
+        let width = (config.width as f64 * WIDTH_RATIO) as i32;
+
+        // 标题一行，剩下的放留言内容，放不下的截断
+        let max_lines =
+            ((self.slots.len() as i32 * lane_size - 2 * padding) / config.font_size as i32).max(2);
+        let mut lines = wrap(&danmu.content, (width - 2 * padding) as f64, config);
+        if lines.len() as i32 > max_lines - 1 {
+            lines.truncate((max_lines - 1) as usize);
+            if let Some(last) = lines.last_mut() {
+                last.pop();
+                last.push('…');
+            }
+        }
+        let text_height = (lines.len() as i32 + 1) * config.font_size as i32 + 2 * padding;
+        let k =
+            ((text_height + lane_size - 1) / lane_size).clamp(1, self.slots.len() as i32) as usize;
+
+        // 找到最早空出来的连续 k 个槽位，相同时优先下方
+        let (FloatOrd(free_at), p) = (0..=self.slots.len() - k)
+            .map(|p| {
+                let free_at = self.slots[p..p + k]
+                    .iter()
+                    .copied()
+                    .fold(f64::MIN, f64::max);
+                (FloatOrd(free_at), p)
+            })
+            .min()?;
+        if free_at > danmu.timeline_s {
+            let time_need = free_at - danmu.timeline_s;
+            // 和滚动弹幕一样只允许延迟 1s
+            if time_need >= 1.0 {
+                debug!("醒目留言区域已满，跳过：{}", danmu.content);
+                return None;
+            }
+            debug!("醒目留言区域已满，延迟 {} 秒", time_need);
+            danmu.timeline_s = free_at;
+        }
+        for slot in &mut self.slots[p..p + k] {
+            *slot = danmu.timeline_s + duration;
+        }
+
+        let height = k as i32 * lane_size;
+        let y = config.height as i32 - p as i32 * lane_size - height;
+        danmu.content = format!("{user} ￥{price}\n{}", lines.join("\n"));
+        danmu.rgb = (0xFF, 0xFF, 0xFF);
+        Some(Drawable::new(
+            danmu,
+            duration,
+            "SuperChat",
+            DrawEffect::Boxed {
+                pos: (0, y),
+                size: (width, height),
+                padding,
+                background,
+            },
+        ))
+    }
+}
+
+/// 按宽度将文本折行
+pub(super) fn wrap(text: &str, max_width: f64, config: &Config) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    let mut line_width = 0.0;
+    let mut buf = [0u8; 4];
+    for ch in text.trim().chars() {
+        if ch == '\n' {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0.0;
+            continue;
+        }
+        let w = text_length(ch.encode_utf8(&mut buf), config);
+        if line_width + w > max_width && !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0.0;
+        }
+        line.push(ch);
+        line_width += w;
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
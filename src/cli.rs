@@ -1,39 +1,67 @@
 use std::{
     cmp::Ordering,
-    collections::HashSet,
+    ffi::OsString,
     fs::File,
     io::{StdoutLock, Write},
+    ops::RangeInclusive,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
+use super::bilibili::{Client, ClientConfig, Credential, DanmakuElem, VideoDanmu};
+use super::dedup::Dedup;
+use super::file_format::FileFormat;
+use super::gift_combo::GiftCombo;
 use super::input_type::InputType;
-use super::CanvasConfig;
+use super::reorder::Reorder;
+use super::{CanvasConfig, Emotes, Filter, FontMetrics, OutputFormat, Pool, ReverseMode};
 use anyhow::{Context, Result};
-use biliapi::Request;
+use chrono::NaiveDate;
 use clap::Parser;
 use either::Either;
+use futures::StreamExt;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 #[derive(Parser, Debug)]
 #[clap(author = "gwy15", version, about = "将 XML 弹幕转换为 ASS 文件")]
+// 配置文件中的参数放在命令行参数之前，允许命令行参数覆盖
+#[clap(args_override_self = true)]
 pub struct Args {
     #[cfg(feature = "web")]
     #[clap(long, help = "不打开 web ui 而使用 cli 模式")]
     pub no_web: bool,
 
     #[clap(
-        help = "需要转换的输入，可以是 xml 文件、文件夹或是哔哩哔哩链接、BV 号。如果是文件夹会递归将其下所有 XML 都进行转换",
+        help = "需要转换的输入，可以是 xml/json/pb/so 弹幕文件、文件夹或是哔哩哔哩链接、BV 号。如果是文件夹会递归将其下所有弹幕文件都进行转换",
         default_value = "."
     )]
     pub input: String,
 
+    #[clap(
+        long = "config",
+        short = 'c',
+        help = "TOML 配置文件。不指定时依次查找输入所在目录下的 danmu2ass.toml 和用户配置目录下的 danmu2ass/config.toml"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[clap(long = "profile", help = "使用配置文件中 [profile.<PROFILE>] 下的配置")]
+    pub profile: Option<String>,
+
     #[clap(
         long = "output",
         short = 'o',
-        help = "输出的 ASS 文件，默认为输入文件名将 .xml 替换为 .ass，如果输入是文件夹则忽略"
+        help = "输出的字幕文件，默认为输入文件名替换为输出格式的扩展名，如果输入是文件夹则忽略"
     )]
     pub ass_file: Option<PathBuf>,
 
+    #[clap(
+        long = "format",
+        help = "输出格式。srt 和 vtt 不支持滚动，弹幕会静态显示",
+        value_enum,
+        default_value = "ass"
+    )]
+    pub format: OutputFormat,
+
     #[clap(long = "width", short = 'w', help = "屏幕宽度", default_value = "1280")]
     width: u32,
 
@@ -51,9 +79,18 @@ pub struct Args {
     #[clap(long = "font-size", help = "弹幕字体大小", default_value = "25")]
     font_size: u32,
 
+    #[clap(
+        long = "font-file",
+        help = "弹幕字体的 TTF/OTF 文件，用于准确计算弹幕宽度。不指定时按字符估算宽度"
+    )]
+    font_file: Option<PathBuf>,
+
+    #[clap(skip)]
+    font_metrics: Option<Arc<FontMetrics>>,
+
     #[clap(
         long = "width-ratio",
-        help = "计算弹幕宽度的比例，为避免重叠可以调大这个数值",
+        help = "计算弹幕宽度的比例，为避免重叠可以调大这个数值。指定 --font-file 时不生效",
         default_value = "1.2"
     )]
     width_ratio: f64,
@@ -89,6 +126,136 @@ pub struct Args {
     )]
     float_percentage: f64,
 
+    #[clap(
+        long = "keep-fixed",
+        help = "保留顶部、底部弹幕为固定弹幕，默认会将它们转为滚动弹幕"
+    )]
+    keep_fixed: bool,
+
+    #[clap(
+        long = "fixed-duration",
+        help = "顶部、底部弹幕以及礼物在屏幕上的持续时间，单位为秒，可以有小数",
+        default_value = "5"
+    )]
+    fixed_duration: f64,
+
+    #[clap(
+        long = "top-percentage",
+        help = "屏幕上顶部弹幕最多高度百分比，需要 --keep-fixed",
+        default_value = "0.3"
+    )]
+    top_percentage: f64,
+
+    #[clap(
+        long = "bottom-percentage",
+        help = "屏幕上底部弹幕最多高度百分比，需要 --keep-fixed",
+        default_value = "0.3"
+    )]
+    bottom_percentage: f64,
+
+    #[clap(
+        long = "reverse",
+        help = "逆向弹幕的处理方式：keep 保留为从左往右滚动，float 转为普通滚动弹幕，drop 丢弃",
+        value_enum,
+        default_value = "float"
+    )]
+    reverse: ReverseMode,
+
+    #[clap(
+        long = "superchat",
+        help = "在屏幕左下角绘制直播录播中的醒目留言（SuperChat）"
+    )]
+    superchat: bool,
+
+    #[clap(
+        long = "superchat-percentage",
+        help = "屏幕左下角醒目留言区域最多高度百分比",
+        default_value = "0.4"
+    )]
+    superchat_percentage: f64,
+
+    #[clap(
+        long = "gift",
+        help = "在屏幕右下角绘制直播录播中的礼物和上舰，底部弹幕和字幕会排在礼物区域上方"
+    )]
+    gift: bool,
+
+    #[clap(
+        long = "gift-percentage",
+        help = "屏幕右下角礼物区域最多高度百分比",
+        default_value = "0.3"
+    )]
+    gift_percentage: f64,
+
+    #[clap(
+        long = "gift-combo-window",
+        help = "合并同一用户连击礼物的时间窗口，单位为秒，0 代表不合并",
+        default_value = "5"
+    )]
+    gift_combo_window: f64,
+
+    #[clap(
+        long = "dedup-window",
+        help = "合并刷屏弹幕的时间窗口，窗口内相同或相近的弹幕合并为一条“内容 ×N”，单位为秒，0 代表不合并",
+        default_value = "0"
+    )]
+    dedup_window: f64,
+
+    #[clap(
+        long = "dedup-similarity",
+        help = "合并刷屏弹幕时的相似度，比较前会去掉空白并压缩重复字符，1 代表只合并这之后相同的弹幕",
+        default_value = "0.8"
+    )]
+    dedup_similarity: f64,
+
+    #[clap(
+        long = "dedup-scale",
+        help = "合并刷屏弹幕之后按数量放大字号，最多放大到刚好占满一行"
+    )]
+    dedup_scale: bool,
+
+    #[clap(
+        long = "pools",
+        help = "包含哪些弹幕池的弹幕，用逗号分隔。字幕弹幕绘制为底部居中的字幕",
+        value_enum,
+        use_value_delimiter = true,
+        default_values = &["normal", "subtitle", "special"]
+    )]
+    pools: Vec<Pool>,
+
+    #[clap(
+        long = "ai-level",
+        visible_alias = "min-weight",
+        help = "智能屏蔽等级 0-10，和网页播放器的屏蔽等级一致，屏蔽权重低于这个等级的视频弹幕，0 代表不屏蔽。没有权重的弹幕（如录播姬的 XML）不受影响",
+        default_value = "0",
+        value_parser = clap::value_parser!(u32).range(0..=10)
+    )]
+    ai_level: u32,
+
+    #[clap(
+        long = "source-font-size",
+        help = "按弹幕原本的字号相对于 25 缩放，保留小字号（18）和大字号（36）弹幕的相对大小，大字号的弹幕会占用多行"
+    )]
+    source_font_size: bool,
+
+    #[clap(
+        long = "command-dm",
+        help = "下载视频弹幕时同时下载互动弹幕（UP 主弹幕、投票、关联视频等），绘制为屏幕右上角的卡片"
+    )]
+    command_dm: bool,
+
+    #[clap(long = "emotes", help = "把弹幕中的表情（如 [doge]）替换为可读的文字")]
+    emotes: bool,
+
+    #[clap(
+        long = "emote-file",
+        help = "表情替换文件，每行一条 \"[表情] => 替换文本\"，替换文本中的 {url} 为视频中表情图片的地址。指定时同时开启 --emotes"
+    )]
+    emote_file: Option<PathBuf>,
+
+    #[clap(skip)]
+    emote_map: Option<Arc<Emotes>>,
+
     #[clap(
         long = "alpha",
         short = 'a',
@@ -99,16 +266,123 @@ pub struct Args {
 
     #[clap(
         long = "force",
-        help = "默认会跳过 ass 比 xml 修改时间更晚的文件，此参数会强制转换"
+        help = "默认会跳过输出文件比输入文件修改时间更晚的文件，此参数会强制转换"
     )]
     pub force: bool,
 
+    #[clap(
+        long = "watch",
+        help = "持续监控输入的文件夹，自动转换新增或修改的弹幕文件，收到 Ctrl-C 或 SIGTERM 时等待转换完成后退出"
+    )]
+    pub watch: bool,
+
+    #[clap(
+        long = "watch-delay",
+        help = "监控模式下，弹幕文件多久没有变化后认为已经写完，单位为秒",
+        default_value = "10"
+    )]
+    pub watch_delay: f64,
+
+    #[clap(
+        long = "stream",
+        help = "流式转换 XML 文件，不把所有弹幕读入内存。要求输入基本按时间排序，录播姬的文件满足这一点"
+    )]
+    pub stream: bool,
+
+    #[clap(
+        long = "reorder-window",
+        help = "流式转换时允许的乱序时间窗口，单位为秒，超出窗口的弹幕不再严格按时间排序",
+        default_value = "10"
+    )]
+    pub reorder_window: f64,
+
     #[clap(
         long = "denylist",
-        help = "黑名单，需要过滤的关键词列表文件，每行一个关键词"
+        help = "过滤规则文件，每行一条规则：关键词、eq:完全匹配、re:正则表达式、uid:用户 UID 或 hash:mid_hash，行尾加上 \" => #RRGGBB\" 改为指定颜色，否则丢弃"
     )]
     denylist: Option<PathBuf>,
 
+    #[clap(
+        long = "save-xml",
+        help = "下载哔哩哔哩视频的弹幕时同时保存为 XML 文件，方便存档以及之后用不同的设置重新转换"
+    )]
+    pub save_xml: bool,
+
+    #[clap(
+        long = "all-pages",
+        help = "下载视频的所有分 p，每个分 p 输出为一个文件"
+    )]
+    pub all_pages: bool,
+
+    #[clap(
+        long = "page-template",
+        help = "下载分 p 时的输出文件名（不含扩展名），可以使用 {title} {bv} {page} {part}。没有指定分 p 的单个视频直接使用视频标题",
+        default_value = "{title} - P{page} {part}"
+    )]
+    pub page_template: String,
+
+    #[clap(
+        long = "timeout",
+        help = "下载弹幕时单个请求的超时时间，单位为秒",
+        default_value = "10"
+    )]
+    pub timeout: f64,
+
+    #[clap(
+        long = "retries",
+        help = "下载弹幕时遇到 412/429/5xx 等错误的重试次数，每次重试的等待时间翻倍",
+        default_value = "3"
+    )]
+    pub retries: u32,
+
+    #[clap(
+        long = "rate-limit",
+        help = "下载弹幕时每秒最多发出的请求数量，0 代表不限制",
+        default_value = "5"
+    )]
+    pub rate_limit: f64,
+
+    #[clap(
+        long = "sessdata",
+        help = "登录后的 SESSDATA cookie，下载弹幕时使用。命令行参数可能被其他用户看到，建议使用 --cookie-file",
+        conflicts_with = "cookie-file"
+    )]
+    sessdata: Option<Credential>,
+
+    #[clap(
+        long = "cookie-file",
+        help = "包含 SESSDATA 的 cookie 文件，可以是浏览器导出的 cookies.txt、Cookie header 或者只有 SESSDATA 的值"
+    )]
+    cookie_file: Option<PathBuf>,
+
+    #[clap(
+        long = "history-date",
+        help = "同时下载这一天或者这几天的历史弹幕，如 2023-01-01 或 2023-01-01..2023-01-31，和当前的弹幕合并去重。需要登录",
+        value_parser = parse_history_date
+    )]
+    pub history_date: Option<RangeInclusive<NaiveDate>>,
+
+    #[clap(
+        long = "api-base-url",
+        hide = true,
+        default_value = "https://api.bilibili.com"
+    )]
+    pub api_base_url: String,
+
+    #[clap(
+        long = "episodes",
+        help = "下载番剧的哪几集，如 3 或 1-12，默认下载整季",
+        value_parser = parse_episodes
+    )]
+    pub episodes: Option<RangeInclusive<u32>>,
+
+    #[clap(
+        long = "concurrency",
+        help = "同时下载的分 p 或剧集数量",
+        default_value = "4"
+    )]
+    pub concurrency: usize,
+
     #[clap(long = "pause", help = "在处理完后暂停等待输入")]
     pub pause: bool,
 
@@ -124,24 +398,151 @@ pub struct Args {
         default_value = "0.0"
     )]
     pub time_offset: f64,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    #[clap(about = "录制直播间的弹幕，保存为录播姬格式的 XML，按 Ctrl+C 停止")]
+    Record(RecordArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RecordArgs {
+    #[clap(help = "直播间号，可以是短号")]
+    pub room_id: u64,
+
+    #[clap(
+        long = "output",
+        short = 'o',
+        help = "保存弹幕的 XML 文件，默认为 <直播间号>-<开始时间>.xml"
+    )]
+    pub output: Option<PathBuf>,
+
+    #[clap(long = "ws-url", hide = true)]
+    pub ws_url: Option<String>,
 }
 
 impl Args {
+    /// 解析命令行参数，并合并配置文件中的参数
+    pub fn parse_with_config_file() -> Result<Self> {
+        let bool_flags = Self::bool_flags();
+        let cli_args: Vec<OsString> = std::env::args_os().collect();
+        let args = Self::parse_from(crate::config_file::resolve_bool_flags(
+            cli_args.clone(),
+            &bool_flags,
+        ));
+
+        let path = match args.config.clone() {
+            Some(path) => path,
+            None => match crate::config_file::discover(&args.input) {
+                Some(path) => path,
+                None => {
+                    if args.profile.is_some() {
+                        anyhow::bail!("指定了 --profile 但是没有找到配置文件");
+                    }
+                    return Ok(args);
+                }
+            },
+        };
+        log::info!("读取配置文件 {}", path.display());
+        let file_args = crate::config_file::load_args(&path, args.profile.as_deref(), &bool_flags)?;
+        log::debug!(
+            "配置文件参数：{:?}",
+            crate::config_file::redacted(&file_args)
+        );
+
+        let merged = crate::config_file::merge_args(cli_args, file_args, &bool_flags);
+        Self::try_parse_from(merged)
+            .with_context(|| format!("配置文件 {} 中有不合法的参数", path.display()))
+    }
+
+    /// 所有开关类参数的长名，可以用 `--no-<flag>` 关闭
+    pub(crate) fn bool_flags() -> Vec<String> {
+        use clap::CommandFactory;
+        Self::command()
+            .get_arguments()
+            .filter(|a| !a.is_takes_value_set())
+            .filter_map(|a| a.get_long())
+            .map(ToString::to_string)
+            .collect()
+    }
+
     pub fn check(&mut self) -> Result<()> {
         if let Some(f) = self.denylist.as_ref() {
             if !f.exists() {
-                anyhow::bail!("黑名单文件不存在");
+                anyhow::bail!("过滤规则文件不存在");
             }
             if f.is_dir() {
-                anyhow::bail!("黑名单文件不能是目录");
+                anyhow::bail!("过滤规则文件不能是目录");
             }
         }
+        if let Some(f) = self.cookie_file.as_ref() {
+            self.sessdata = Some(Credential::from_cookie_file(f)?);
+            log::info!("从 {} 读取登录凭据", f.display());
+        }
+        if let Some(f) = self.font_file.as_ref() {
+            let metrics = FontMetrics::from_path(f)?;
+            log::info!("从字体文件 {} 读取字形宽度", f.display());
+            self.font_metrics = Some(Arc::new(metrics));
+        }
+        if let Some(f) = self.emote_file.as_ref() {
+            let text = std::fs::read_to_string(f)
+                .with_context(|| format!("读取表情文件 {} 失败", f.display()))?;
+            let emotes = Emotes::parse(&text)
+                .with_context(|| format!("表情文件 {} 解析错误", f.display()))?;
+            log::info!("表情替换载入 {} 条", emotes.len());
+            self.emote_map = Some(Arc::new(emotes));
+        } else if self.emotes {
+            self.emote_map = Some(Arc::new(Emotes::default()));
+        }
+        if self.watch && !Path::new(&self.input).is_dir() {
+            anyhow::bail!("监控模式只支持文件夹输入");
+        }
+        if self.reorder_window < 0.0 {
+            anyhow::bail!("乱序时间窗口不能小于 0");
+        }
+        if self.watch_delay < 0.0 {
+            anyhow::bail!("监控等待时间不能小于 0");
+        }
+        if !(0.0..=1.0).contains(&self.superchat_percentage) {
+            anyhow::bail!("醒目留言区域最大高度百分比应该在 0 到 1 之间");
+        }
+        if !(0.0..=1.0).contains(&self.gift_percentage) {
+            anyhow::bail!("礼物区域最大高度百分比应该在 0 到 1 之间");
+        }
+        if self.history_date.is_some() && self.sessdata.is_none() {
+            anyhow::bail!("下载历史弹幕需要登录，请指定 --cookie-file 或 --sessdata");
+        }
+        if self.timeout <= 0.0 {
+            anyhow::bail!("超时时间应该大于 0");
+        }
+        if self.rate_limit < 0.0 {
+            anyhow::bail!("每秒请求数量不能小于 0");
+        }
+        if self.concurrency == 0 {
+            anyhow::bail!("同时下载的数量不能为 0");
+        }
+        if self.dedup_window < 0.0 {
+            anyhow::bail!("合并刷屏弹幕的时间窗口不能小于 0");
+        }
+        if !(0.0..=1.0).contains(&self.dedup_similarity) || self.dedup_similarity == 0.0 {
+            anyhow::bail!("合并刷屏弹幕的相似度应该在 0 到 1 之间");
+        }
         if self.float_percentage < 0.0 {
             anyhow::bail!("滚动弹幕最大高度百分比不能小于 0");
         }
         if self.float_percentage > 1.0 {
             anyhow::bail!("滚动弹幕最大高度百分比不能大于 1");
         }
+        if !(0.0..=1.0).contains(&self.top_percentage) {
+            anyhow::bail!("顶部弹幕最大高度百分比应该在 0 到 1 之间");
+        }
+        if !(0.0..=1.0).contains(&self.bottom_percentage) {
+            anyhow::bail!("底部弹幕最大高度百分比应该在 0 到 1 之间");
+        }
 
         Ok(())
     }
@@ -152,42 +553,97 @@ impl Args {
             height: self.height,
             font: self.font.clone(),
             font_size: self.font_size,
+            font_metrics: self.font_metrics.clone(),
             width_ratio: self.width_ratio,
             horizontal_gap: self.horizontal_gap,
             duration: self.duration,
             lane_size: self.lane_size,
             float_percentage: self.float_percentage,
             opacity: ((1.0 - self.alpha) * 255.0) as u8,
-            bottom_percentage: 0.3,
+            top_percentage: self.top_percentage,
+            bottom_percentage: self.bottom_percentage,
+            keep_fixed: self.keep_fixed,
+            fixed_duration: self.fixed_duration,
+            reverse_mode: self.reverse,
+            superchat: self.superchat,
+            superchat_percentage: self.superchat_percentage,
+            command: self.command_dm,
+            emotes: self.emote_map.clone(),
+            gift: self.gift,
+            gift_percentage: self.gift_percentage,
+            gift_combo_window: self.gift_combo_window,
+            dedup_window: self.dedup_window,
+            dedup_similarity: self.dedup_similarity,
+            dedup_scale: self.dedup_scale,
+            pools: self.pools.clone(),
+            ai_level: self.ai_level,
+            source_font_size: self.source_font_size,
             outline: self.outline,
             bold: self.bold,
             time_offset: self.time_offset,
         }
     }
 
-    fn denylist(&self) -> Result<Option<HashSet<String>>> {
+    /// 哔哩哔哩接口的配置，包括登录凭据，web 模式下载弹幕时也会使用
+    pub fn client_config(&self) -> ClientConfig {
+        ClientConfig {
+            base_url: self.api_base_url.clone(),
+            timeout: std::time::Duration::from_secs_f64(self.timeout),
+            retries: self.retries,
+            rate_limit: self.rate_limit,
+            credential: self.sessdata.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn client(&self) -> Result<Client> {
+        Client::new(self.client_config())
+    }
+
+    /// 流式转换时的乱序时间窗口，None 代表读入所有弹幕再排序
+    fn reorder_window(&self) -> Option<f64> {
+        self.stream.then_some(self.reorder_window)
+    }
+
+    fn filter(&self) -> Result<Option<Filter>> {
         match self.denylist.as_ref() {
             None => Ok(None),
             Some(path) => {
-                let denylist = std::fs::read_to_string(path)?;
-                let list: HashSet<String> = denylist
-                    .split('\n')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect();
-                log::info!("黑名单载入 {} 个", list.len());
-                log::debug!("黑名单：{:?}", list);
-                Ok(Some(list))
+                let rules = std::fs::read_to_string(path)?;
+                let filter = Filter::parse(&rules)
+                    .with_context(|| format!("过滤规则 {} 解析错误", path.display()))?;
+                log::info!("过滤规则载入 {} 条", filter.len());
+                Ok(Some(filter))
             }
         }
     }
 
     pub async fn process(self) -> Result<()> {
-        match self.input.parse::<InputType>()? {
+        if let Some(Command::Record(record)) = self.command.as_ref() {
+            return self.record(record).await;
+        }
+        match self
+            .input
+            .parse::<InputType>()?
+            .resolve(&self.client()?)
+            .await?
+        {
             InputType::File(file) => {
-                let denylist = self.denylist()?;
+                let filter = self.filter()?;
                 let canvas_config = self.canvas_config();
-                convert_xml(&file, self.ass_file, self.force, canvas_config, &denylist)?;
+                let reorder_window = self.reorder_window();
+                convert_file(
+                    &file,
+                    self.ass_file,
+                    self.format,
+                    self.force,
+                    canvas_config,
+                    &filter,
+                    reorder_window,
+                )?;
+            }
+            InputType::Folder(path) if self.watch => {
+                self.watch_folder(path).await?;
             }
             InputType::Folder(path) => {
                 self.process_folder(path)?;
@@ -202,6 +658,9 @@ impl Args {
             InputType::Episode { episode_id } => {
                 self.process_episode_or_season("ep_id", episode_id).await?;
             }
+            InputType::ShortLink(url) => {
+                anyhow::bail!("短链接 {} 没有解析", url);
+            }
         }
 
         Ok(())
@@ -209,26 +668,32 @@ impl Args {
 
     fn process_folder(&self, folder: PathBuf) -> Result<()> {
         let canvas_config = self.canvas_config();
-        let denylist = self.denylist()?;
+        let filter = self.filter()?;
 
         // Windows 下 canonicalize 会莫名其妙，见 https://stackoverflow.com/questions/1816691/how-do-i-resolve-a-canonical-filename-in-windows
         #[cfg(not(windows))]
         let folder = folder.canonicalize()?;
 
         log::info!("递归处理目录 {}", folder.display());
-        let glob = format!("{}/**/*.xml", folder.display());
-
-        let targets: Vec<PathBuf> = glob::glob(&glob)?.collect::<Result<_, _>>()?;
-        log::info!("共找到 {} 个文件", targets.len());
+        let targets = FileFormat::find_files(&folder)?;
+        log::info!("共找到 {} 个弹幕文件", targets.len());
         if targets.is_empty() {
-            anyhow::bail!("没有找到任何文件");
+            anyhow::bail!("没有找到任何弹幕文件");
         }
 
         let t = std::time::Instant::now();
         let (file_count, danmu_count) = targets
             .into_par_iter()
             .map(|path| {
-                match convert_xml(&path, None, self.force, canvas_config.clone(), &denylist) {
+                match convert_file(
+                    &path,
+                    None,
+                    self.format,
+                    self.force,
+                    canvas_config.clone(),
+                    &filter,
+                    self.reorder_window(),
+                ) {
                     Ok(danmu_count) => (1usize, danmu_count),
                     Err(e) => {
                         log::error!("文件 {} 转换错误：{:?}", path.display(), e);
@@ -248,37 +713,246 @@ impl Args {
         Ok(())
     }
 
-    async fn process_bv(&self, bv: String, p: Option<u32>) -> Result<()> {
-        let p = p.unwrap_or(1);
+    async fn watch_folder(&self, folder: PathBuf) -> Result<()> {
+        // 先转换一遍已有的文件
+        if let Err(e) = self.process_folder(folder.clone()) {
+            log::warn!("{:?}", e);
+        }
+
+        #[cfg(not(windows))]
+        let folder = folder.canonicalize()?;
+
+        let canvas_config = self.canvas_config();
+        let filter = self.filter()?;
+        let delay = std::time::Duration::from_secs_f64(self.watch_delay);
+        let (format, force, reorder_window) = (self.format, self.force, self.reorder_window());
+        crate::watch::watch_folder(&folder, delay, move |path| {
+            if let Err(e) = convert_file(
+                path,
+                None,
+                format,
+                force,
+                canvas_config.clone(),
+                &filter,
+                reorder_window,
+            ) {
+                log::error!("文件 {} 转换错误：{:?}", path.display(), e);
+            }
+        })
+        .await
+    }
+
+    async fn record(&self, args: &RecordArgs) -> Result<()> {
+        let client = self.client()?;
+        let path = args.output.clone().unwrap_or_else(|| {
+            let start = chrono::DateTime::<chrono::Utc>::from(std::time::SystemTime::now());
+            PathBuf::from(format!(
+                "{}-{}.xml",
+                args.room_id,
+                start.format("%Y%m%d-%H%M%S")
+            ))
+        });
+        if !self.force && path.exists() {
+            anyhow::bail!("文件 {} 已存在，使用 --force 覆盖", path.display());
+        }
+        let file = std::io::BufWriter::new(File::create(&path)?);
+        let mut writer = crate::live::XmlWriter::new(file, args.room_id)?;
+        log::info!(
+            "开始录制直播间 {} 的弹幕，保存到 {}",
+            args.room_id,
+            path.display()
+        );
+        let result = crate::live::record(
+            &client,
+            args.room_id,
+            args.ws_url.as_deref(),
+            &mut writer,
+            crate::watch::shutdown_signal(),
+        )
+        .await;
+        writer.finish()?;
+        result
+    }
+
+    async fn process_bv(&self, bv: String, p: Option<RangeInclusive<u32>>) -> Result<()> {
         // get info for video
-        let client = biliapi::connection::new_client()?;
-        let mut info = biliapi::requests::VideoInfo::request(&client, bv.clone()).await?;
-        if p > info.pages.len() as u32 {
-            anyhow::bail!("视频 {} 只有 {} p，指定 {}p", bv, info.pages.len(), p);
+        let client = self.client()?;
+        let info = client.video_info(&bv).await?;
+        let total = info.pages.len() as u32;
+        let p_given = p.is_some() || self.all_pages;
+        let pages = match p {
+            _ if self.all_pages => 1..=total,
+            Some(p) => p,
+            None => 1..=1,
+        };
+        if *pages.end() > total {
+            anyhow::bail!("视频 {} 只有 {} p，指定 {}p", bv, total, pages.end());
+        }
+        let width = total.to_string().len().max(2);
+        let page_name = |p: u32, part: &str| {
+            render_page_template(
+                &self.page_template,
+                &[
+                    ("title", &info.title),
+                    ("bv", &bv),
+                    ("page", &format!("{p:0width$}")),
+                    ("part", part),
+                ],
+            )
+        };
+        if pages.start() == pages.end() {
+            let p = *pages.start();
+            let page = &info.pages[p as usize - 1];
+            let danmu = self
+                .fetch_danmu(&client, info.aid, page.cid, page.duration.as_secs())
+                .await?;
+            // 没有指定分 p 时直接用视频标题，指定了分 p 时和多 p 一样使用模板
+            let (name, title) = if p_given {
+                (
+                    page_name(p, &page.part),
+                    format!("{} - P{} {}", info.title, p, page.part),
+                )
+            } else {
+                (info.title.clone(), info.title.clone())
+            };
+            self.convert_downloaded(&name, title, danmu)?;
+            return Ok(());
         }
-        let page = info.pages.swap_remove(p as usize - 1);
 
-        let danmu = crate::bilibili::get_danmu_for_video(page.cid, page.duration.as_secs()).await?;
-        let danmu = danmu.into_iter().map(|d| Ok(d.into()));
+        let downloads = info
+            .pages
+            .iter()
+            .zip(1..)
+            .filter(|(_, p)| pages.contains(p))
+            .map(|(page, p)| Download {
+                name: page_name(p, &page.part),
+                title: format!("{} - P{} {}", info.title, p, page.part),
+                aid: info.aid,
+                cid: page.cid,
+                duration_sec: page.duration.as_secs(),
+            })
+            .collect();
+        self.download_all(&client, downloads).await
+    }
+
+    /// 并发下载多个视频的弹幕，跳过输出文件已经存在的视频，结束时输出统计
+    async fn download_all(&self, client: &Client, downloads: Vec<Download>) -> Result<()> {
+        if self.ass_file.is_some() {
+            anyhow::bail!("下载多个视频时不能指定输出文件");
+        }
+        let total = downloads.len();
+        let (downloads, skipped): (Vec<_>, Vec<_>) = downloads.into_iter().partition(|d| {
+            self.force || !Path::new(&format!("{}.{}", d.name, self.format.extension())).exists()
+        });
+        for d in skipped.iter() {
+            log::info!("{} 已存在，跳过", d.name);
+        }
+        log::info!("共 {} 个视频，需要下载 {} 个", total, downloads.len());
+
+        let t = std::time::Instant::now();
+        let mut results = futures::stream::iter(downloads.into_iter().map(|d| async move {
+            let danmu = self.fetch_danmu(client, d.aid, d.cid, d.duration_sec).await;
+            (d, danmu)
+        }))
+        .buffer_unordered(self.concurrency);
+
+        let (mut converted, mut incomplete, mut failed) = (0, 0, 0);
+        while let Some((d, danmu)) = results.next().await {
+            match danmu.and_then(|danmu| {
+                if !danmu.is_complete() {
+                    incomplete += 1;
+                }
+                self.convert_downloaded(&d.name, d.title, danmu)
+            }) {
+                Ok(()) => converted += 1,
+                Err(e) => {
+                    log::error!("{} 转换错误：{:?}", d.name, e);
+                    failed += 1;
+                }
+            }
+        }
+        log::info!(
+            "共 {} 个视频：转换 {} 个（其中 {} 个弹幕不完整），跳过 {} 个，失败 {} 个，耗时 {:?}",
+            total,
+            converted,
+            incomplete,
+            skipped.len(),
+            failed,
+            t.elapsed()
+        );
+        if failed > 0 {
+            anyhow::bail!("{} 个视频转换失败", failed);
+        }
+        Ok(())
+    }
+
+    /// 下载视频的弹幕，指定了 `--history-date` 时同时下载历史弹幕并合并，
+    /// 指定了 `--command-dm` 或者替换表情时同时下载互动弹幕和表情
+    async fn fetch_danmu(
+        &self,
+        client: &Client,
+        aid: u64,
+        cid: u64,
+        duration_sec: u64,
+    ) -> Result<VideoDanmu> {
+        let mut danmu = crate::bilibili::get_danmu_for_video(client, cid, duration_sec).await?;
+        if let Some(dates) = self.history_date.clone() {
+            let history = crate::bilibili::get_history_danmu_for_video(client, cid, dates).await?;
+            let count = danmu.elems.len();
+            danmu.elems = crate::bilibili::merge(danmu.elems.into_iter().chain(history));
+            log::info!(
+                "合并历史弹幕之后共 {} 条弹幕，新增 {} 条",
+                danmu.elems.len(),
+                danmu.elems.len().saturating_sub(count)
+            );
+        }
+        if self.command_dm || self.emote_map.is_some() {
+            match crate::bilibili::get_dm_view(client, aid, cid).await {
+                Ok(view) => danmu.view = view,
+                // 互动弹幕和表情不影响普通弹幕，失败时只输出警告
+                Err(e) => log::warn!("视频 cid={} 的互动弹幕和表情下载失败：{:?}", cid, e),
+            }
+        }
+        Ok(danmu)
+    }
+
+    /// 保存并转换下载的弹幕，没有指定输出文件时输出到 `{name}.{扩展名}`
+    fn convert_downloaded(&self, name: &str, title: String, danmu: VideoDanmu) -> Result<()> {
+        if !danmu.is_complete() {
+            log::warn!(
+                "{} 有 {} 个分段下载失败，弹幕不完整",
+                name,
+                danmu.failed_segments.len()
+            );
+        }
+        self.save_xml(name, &danmu.elems)?;
+        let mut canvas_config = self.canvas_config();
+        if let Some(emotes) = canvas_config.emotes.as_ref() {
+            canvas_config.emotes = Some(Arc::new(emotes.with_expressions(&danmu.view.expressions)));
+        }
+        let danmu = danmu
+            .elems
+            .into_iter()
+            .map(|d| Ok(d.into()))
+            .chain(danmu.view.commands.into_iter().map(|d| Ok(d.into())));
 
         let output = match writer_from_path(self.ass_file.as_deref())? {
             Some(w) => w,
             _ => {
-                let filename = format!("{}.ass", info.title);
-                let ass = PathBuf::from(&filename);
-                let f = std::fs::File::create(ass)
+                let filename = format!("{}.{}", name, self.format.extension());
+                let f = std::fs::File::create(&filename)
                     .with_context(|| format!("Create output ass file `{filename}` failed"))?;
                 Either::Left(f)
             }
         };
         convert(
             danmu,
-            info.title,
+            title,
             output,
-            self.canvas_config(),
-            &self.denylist()?,
+            self.format,
+            canvas_config,
+            &self.filter()?,
         )?;
-
         Ok(())
     }
 
@@ -288,50 +962,117 @@ impl Args {
         key_type: &'static str,
         ep_or_season_id: u64,
     ) -> Result<()> {
-        let client = biliapi::connection::new_client()?;
-
-        let mut season_info =
-            crate::bilibili::Season::request(&client, (key_type, ep_or_season_id))
-                .await
-                .context("获取 season 失败")?;
-        let (title, ep) = match key_type {
-            "season_id" => (season_info.title, season_info.episodes.swap_remove(0)),
-            "ep_id" => {
-                let ep = season_info
-                    .episodes
-                    .into_iter()
-                    .find(|ep| ep.id == ep_or_season_id)
-                    .ok_or_else(|| anyhow::anyhow!("没有找到 ep_id {}", ep_or_season_id))?;
-                (format!("{} - {}", season_info.title, ep.title), ep)
-            }
-            _ => unreachable!(),
-        };
+        let client = self.client()?;
 
-        let danmu = crate::bilibili::get_danmu_for_video(ep.cid, ep.duration_ms / 1000).await?;
-        let danmu = danmu.into_iter().map(|d| Ok(d.into()));
+        let season_info = crate::bilibili::Season::request(&client, (key_type, ep_or_season_id))
+            .await
+            .context("获取 season 失败")?;
 
-        let output = match writer_from_path(self.ass_file.as_deref())? {
-            Some(w) => w,
-            _ => {
-                let filename = format!("{}.ass", title);
-                let ass = std::fs::File::create(&filename)
-                    .with_context(|| format!("Create output ass filename `{filename}` failed"))?;
-                Either::Left(ass)
-            }
-        };
+        if key_type == "ep_id" {
+            let ep = season_info
+                .episodes
+                .into_iter()
+                .find(|ep| ep.id == ep_or_season_id)
+                .ok_or_else(|| anyhow::anyhow!("没有找到 ep_id {}", ep_or_season_id))?;
+            let title = format!("{} - {}", season_info.title, ep.title);
+            let danmu = self
+                .fetch_danmu(&client, ep.aid, ep.cid, ep.duration_ms / 1000)
+                .await?;
+            return self.convert_downloaded(&title, title.clone(), danmu);
+        }
 
-        convert(
-            danmu,
-            title,
-            output,
-            self.canvas_config(),
-            &self.denylist()?,
-        )?;
+        let total = season_info.episodes.len() as u32;
+        let episodes = self.episodes.clone().unwrap_or(1..=total);
+        if *episodes.end() > total {
+            anyhow::bail!(
+                "{} 只有 {} 集，指定第 {} 集",
+                season_info.title,
+                total,
+                episodes.end()
+            );
+        }
+        let width = total.to_string().len().max(2);
+        let downloads: Vec<_> = season_info
+            .episodes
+            .into_iter()
+            .zip(1..)
+            .filter(|(_, i)| episodes.contains(i))
+            .map(|(ep, i)| {
+                let name = format!(
+                    "{} - {:0width$} {}",
+                    sanitize_filename(&season_info.title),
+                    i,
+                    sanitize_filename(ep.display_title())
+                );
+                Download {
+                    name,
+                    title: format!("{} - {}", season_info.title, ep.display_title()),
+                    aid: ep.aid,
+                    cid: ep.cid,
+                    duration_sec: ep.duration_ms / 1000,
+                }
+            })
+            .collect();
+        if downloads.len() == 1 && self.ass_file.is_some() {
+            let d = downloads.into_iter().next().unwrap();
+            let danmu = self
+                .fetch_danmu(&client, d.aid, d.cid, d.duration_sec)
+                .await?;
+            return self.convert_downloaded(&d.name, d.title, danmu);
+        }
+        self.download_all(&client, downloads).await
+    }
 
+    /// 指定了 `--save-xml` 时，将下载的弹幕保存到输出文件旁边，没有指定输出文件时保存到 `{title}.xml`
+    fn save_xml(&self, title: &str, danmu: &[DanmakuElem]) -> Result<()> {
+        if !self.save_xml {
+            return Ok(());
+        }
+        let path = match self.ass_file.as_deref() {
+            Some(output) if output.to_string_lossy() != "-" => output.with_extension("xml"),
+            _ => PathBuf::from(format!("{title}.xml")),
+        };
+        let f = File::create(&path)
+            .with_context(|| format!("创建 XML 文件 {} 失败", path.display()))?;
+        crate::bilibili::write_xml(std::io::BufWriter::new(f), danmu)?;
+        log::info!("保存 {} 条弹幕到 {}", danmu.len(), path.display());
         Ok(())
     }
 }
 
+fn parse_history_date(s: &str) -> Result<RangeInclusive<NaiveDate>, String> {
+    crate::bilibili::parse_date_range(s).map_err(|e| e.to_string())
+}
+
+fn parse_episodes(s: &str) -> Result<RangeInclusive<u32>, String> {
+    crate::input_type::parse_range(s).ok_or_else(|| format!("不合法的剧集范围 {s}"))
+}
+
+/// 下载任务
+struct Download {
+    /// 输出文件名，不含扩展名
+    name: String,
+    title: String,
+    aid: u64,
+    cid: u64,
+    duration_sec: u64,
+}
+
+/// 替换模板中的 `{key}`，替换的内容会去掉不能作为文件名的字符
+fn render_page_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut name = template.to_string();
+    for (key, value) in values {
+        name = name.replace(&format!("{{{key}}}"), &sanitize_filename(value));
+    }
+    name.trim().to_string()
+}
+
+/// 将不能作为文件名的字符替换为 `_`
+fn sanitize_filename(s: &str) -> String {
+    s.trim()
+        .replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_")
+}
+
 fn writer_from_path(path: Option<&Path>) -> Result<Option<Either<File, StdoutLock<'_>>>> {
     let Some(output) = path else {
         return Ok(None);
@@ -344,32 +1085,34 @@ fn writer_from_path(path: Option<&Path>) -> Result<Option<Either<File, StdoutLoc
     Ok(Some(writer))
 }
 
-fn convert_xml(
+fn convert_file(
     file: &Path,
     output: Option<PathBuf>,
+    format: OutputFormat,
     force: bool,
     canvas_config: CanvasConfig,
-    denylist: &Option<HashSet<String>>,
+    filter: &Option<Filter>,
+    reorder_window: Option<f64>,
 ) -> Result<usize> {
     if !file.exists() {
         anyhow::bail!("文件 {} 不存在", file.display());
     }
 
-    let output = output.unwrap_or_else(|| file.with_extension("ass"));
+    let output = output.unwrap_or_else(|| file.with_extension(format.extension()));
     if output.is_dir() {
         anyhow::bail!("输出文件 {} 不能是一个目录", output.display());
     }
     log::info!("转换 {} => {}", file.display(), output.display());
     // 判断是否需要转换
     if !force && output.exists() {
-        let xml_modified = file.metadata()?.modified()?;
-        let ass_modified = output.metadata()?.modified()?;
-        if xml_modified < ass_modified {
-            log::info!("ASS 文件比 XML 文件新，跳过转换（{}）", file.display());
+        let input_modified = file.metadata()?.modified()?;
+        let output_modified = output.metadata()?.modified()?;
+        if input_modified < output_modified {
+            log::info!("输出文件比输入文件新，跳过转换（{}）", file.display());
             return Ok(0);
         }
     }
-    let data_provider = crate::Parser::from_path(file)?;
+    let data_provider = FileFormat::detect(file)?.open(file, canvas_config.gift)?;
     let title = file
         .file_stem()
         .context("无法解析出文件名")?
@@ -377,43 +1120,172 @@ fn convert_xml(
         .to_string();
 
     let writer = writer_from_path(Some(&output))?.unwrap();
-    convert(data_provider, title, writer, canvas_config, denylist)
+    match reorder_window {
+        Some(window) => convert_streaming(
+            data_provider,
+            title,
+            writer,
+            format,
+            canvas_config,
+            filter,
+            window,
+        ),
+        None => convert(data_provider, title, writer, format, canvas_config, filter),
+    }
 }
 
 pub fn convert<I, O>(
     data_provider: I,
     title: String,
     output: O,
+    format: OutputFormat,
     canvas_config: CanvasConfig,
-    denylist: &Option<HashSet<String>>,
+    filter: &Option<Filter>,
 ) -> Result<usize>
 where
     I: Iterator<Item = Result<crate::Danmu>>,
     O: Write,
 {
-    let mut writer = super::AssWriter::new(output, title.clone(), canvas_config.clone())?;
-
-    let mut count = 0;
-    let mut canvas = canvas_config.canvas();
     let t = std::time::Instant::now();
-
     let mut danmus = data_provider.collect::<Result<Vec<_>>>()?;
     danmus.sort_by(|a, b| {
         a.timeline_s
             .partial_cmp(&b.timeline_s)
             .unwrap_or(Ordering::Equal)
     });
+    draw_all(
+        danmus.into_iter().map(Ok),
+        title,
+        output,
+        format,
+        canvas_config,
+        filter,
+        0.0,
+        t,
+    )
+}
+
+/// 流式转换，输入需要基本按时间排序，只在内存中保留 `reorder_window` 秒内的弹幕用于重新排序
+pub fn convert_streaming<I, O>(
+    data_provider: I,
+    title: String,
+    output: O,
+    format: OutputFormat,
+    canvas_config: CanvasConfig,
+    filter: &Option<Filter>,
+    reorder_window: f64,
+) -> Result<usize>
+where
+    I: Iterator<Item = Result<crate::Danmu>>,
+    O: Write,
+{
+    let t = std::time::Instant::now();
+    draw_all(
+        Reorder::new(data_provider, reorder_window),
+        title,
+        output,
+        format,
+        canvas_config,
+        filter,
+        reorder_window,
+        t,
+    )
+}
+
+/// `reorder_window` 为输入可能乱序的时间，静态格式的字幕需要多缓存这么久再排序写入
+#[allow(clippy::too_many_arguments)]
+fn draw_all<I, O>(
+    danmus: I,
+    title: String,
+    output: O,
+    format: OutputFormat,
+    canvas_config: CanvasConfig,
+    filter: &Option<Filter>,
+    reorder_window: f64,
+    t: std::time::Instant,
+) -> Result<usize>
+where
+    I: Iterator<Item = Result<crate::Danmu>>,
+    O: Write,
+{
+    let count = format.with_writer(output, title.clone(), canvas_config.clone(), |writer| {
+        draw_to(danmus, writer, canvas_config, filter, reorder_window)
+    })?;
+    match peak_memory() {
+        Some(peak) => log::info!(
+            "弹幕数量：{}, 耗时 {:?}, 峰值内存 {:.1} MiB（{}）",
+            count,
+            t.elapsed(),
+            peak as f64 / (1 << 20) as f64,
+            title
+        ),
+        None => log::info!("弹幕数量：{}, 耗时 {:?}（{}）", count, t.elapsed(), title),
+    }
+    Ok(count)
+}
+
+fn draw_to<I>(
+    danmus: I,
+    writer: &mut dyn crate::Writer,
+    canvas_config: CanvasConfig,
+    filter: &Option<Filter>,
+    reorder_window: f64,
+) -> Result<usize>
+where
+    I: Iterator<Item = Result<crate::Danmu>>,
+{
+    let mut count = 0;
+    let mut canvas = canvas_config.canvas();
+
+    // 先过滤，被过滤掉的弹幕不参与合并
+    let config = canvas.config.clone();
+    let danmus = danmus.filter_map(|danmu| match danmu {
+        Ok(danmu) if !config.accepts(&danmu) => None,
+        Ok(mut danmu) => match filter.as_ref() {
+            Some(filter) if !filter.apply(&mut danmu) => None,
+            _ => Some(Ok(danmu)),
+        },
+        Err(e) => Some(Err(e)),
+    });
+    let mut danmus: Box<dyn Iterator<Item = Result<crate::Danmu>> + '_> = Box::new(danmus);
+    if canvas.config.gift && canvas.config.gift_combo_window > 0.0 {
+        danmus = Box::new(GiftCombo::new(danmus, canvas.config.gift_combo_window));
+    }
+    if canvas.config.dedup_window > 0.0 {
+        // 放大之后不能超出一行
+        let max_scale = canvas
+            .config
+            .dedup_scale
+            .then(|| canvas.config.lane_size as f64 / canvas.config.font_size as f64);
+        danmus = Box::new(Dedup::new(
+            danmus,
+            canvas.config.dedup_window,
+            canvas.config.dedup_similarity,
+            max_scale,
+        ));
+    }
     for danmu in danmus {
-        if let Some(denylist) = denylist.as_ref() {
-            if denylist.iter().any(|s| danmu.content.contains(s)) {
-                continue;
-            }
-        }
+        let danmu = danmu?;
+        // 绘制只会延迟弹幕，比这条弹幕早开始的字幕都已经画完了；乱序输入时多等一个窗口
+        writer.advance(danmu.timeline_s + canvas.config.time_offset - reorder_window)?;
         if let Some(drawable) = canvas.draw(danmu)? {
             count += 1;
             writer.write(drawable)?;
         }
     }
-    log::info!("弹幕数量：{}, 耗时 {:?}（{}）", count, t.elapsed(), title);
     Ok(count)
 }
+
+/// 进程的峰值内存（字节），目前只支持 Linux
+fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: u64 = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
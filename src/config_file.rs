@@ -0,0 +1,282 @@
+//! 从 TOML 配置文件中读取命令行参数
+//!
+//! 配置文件中的 key 和命令行参数同名（`font_size` 和 `font-size` 都可以），例如
+//!
+//! ```toml
+//! font_size = 36
+//! bold = true
+//!
+//! [profile.1080p]
+//! width = 1920
+//! height = 1080
+//! ```
+//!
+//! 顶层的配置总是生效，`--profile 1080p` 会在此基础上覆盖 `[profile.1080p]` 中的配置。
+//! 配置文件中的值会被转换为命令行参数放在用户参数之前，因此命令行参数的优先级更高。
+//! 开关类的参数可以用 `--no-bold` 或 `--bold=false` 在命令行中关闭。
+use anyhow::{bail, Context, Result};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+use toml::Value;
+
+/// 输入目录下的配置文件名
+const FOLDER_CONFIG_NAME: &str = "danmu2ass.toml";
+
+/// 不能在配置文件中指定的参数
+const RESERVED_KEYS: &[&str] = &["input", "config", "profile"];
+
+/// 值为登录凭据的参数，输出日志时需要隐藏
+const SECRET_KEYS: &[&str] = &["sessdata"];
+
+/// 查找配置文件：先找输入所在目录下的 `danmu2ass.toml`，再找用户配置目录下的 `danmu2ass/config.toml`
+pub fn discover(input: &str) -> Option<PathBuf> {
+    let input = Path::new(input);
+    let folder = if input.is_dir() {
+        Some(input)
+    } else if input.is_file() {
+        input.parent()
+    } else {
+        None
+    };
+    folder
+        .map(|f| f.join(FOLDER_CONFIG_NAME))
+        .into_iter()
+        .chain(dirs::config_dir().map(|d| d.join("danmu2ass").join("config.toml")))
+        .find(|candidate| candidate.is_file())
+}
+
+/// 读取配置文件，转换为命令行参数
+///
+/// `bool_flags` 为所有开关类参数的长名，只有这些参数可以写成 `true`/`false`
+pub fn load_args(path: &Path, profile: Option<&str>, bool_flags: &[String]) -> Result<Vec<String>> {
+    let s = std::fs::read_to_string(path)
+        .with_context(|| format!("读取配置文件 {} 失败", path.display()))?;
+    args_from_str(&s, profile, bool_flags)
+        .with_context(|| format!("解析配置文件 {} 失败", path.display()))
+}
+
+/// 隐藏参数中的登录凭据，用于输出日志
+pub fn redacted(args: &[String]) -> Vec<String> {
+    args.iter()
+        .map(|arg| match arg.split_once('=') {
+            Some((flag, _)) if SECRET_KEYS.contains(&flag.trim_start_matches('-')) => {
+                format!("{flag}=***")
+            }
+            _ => arg.clone(),
+        })
+        .collect()
+}
+
+/// 把配置文件的参数放在用户参数之前，并处理开关参数的关闭
+///
+/// `cli_args` 的第一个为程序名，`bool_flags` 为所有开关类参数的长名
+pub fn merge_args(
+    mut cli_args: Vec<OsString>,
+    file_args: Vec<String>,
+    bool_flags: &[String],
+) -> Vec<OsString> {
+    let user_args = cli_args.split_off(1.min(cli_args.len()));
+    cli_args.extend(file_args.into_iter().map(OsString::from));
+    cli_args.extend(user_args);
+    resolve_bool_flags(cli_args, bool_flags)
+}
+
+/// 处理 `--no-<flag>` 和 `--<flag>=true/false`：关闭时去掉前面出现过的 `--<flag>`，后出现的优先
+pub fn resolve_bool_flags(args: Vec<OsString>, bool_flags: &[String]) -> Vec<OsString> {
+    let is_bool = |name: &str| bool_flags.iter().any(|f| f == name);
+    let mut out: Vec<OsString> = Vec::with_capacity(args.len());
+    let mut iter = args.into_iter();
+    for arg in iter.by_ref() {
+        let Some(s) = arg.to_str() else {
+            out.push(arg);
+            continue;
+        };
+        if s == "--" {
+            out.push(arg);
+            break;
+        }
+        let switch = match s.strip_prefix("--") {
+            // 本身就是参数的（如 --no-web）不处理
+            Some(name) if is_bool(name) => None,
+            Some(name) => match name.split_once('=') {
+                Some((flag, "true")) if is_bool(flag) => Some((flag.to_string(), true)),
+                Some((flag, "false")) if is_bool(flag) => Some((flag.to_string(), false)),
+                Some(_) => None,
+                None => name
+                    .strip_prefix("no-")
+                    .filter(|flag| is_bool(flag))
+                    .map(|flag| (flag.to_string(), false)),
+            },
+            None => None,
+        };
+        match switch {
+            Some((flag, on)) => {
+                let long = format!("--{flag}");
+                out.retain(|a| a.to_str() != Some(long.as_str()));
+                if on {
+                    out.push(long.into());
+                }
+            }
+            None => out.push(arg),
+        }
+    }
+    out.extend(iter);
+    out
+}
+
+fn args_from_str(s: &str, profile: Option<&str>, bool_flags: &[String]) -> Result<Vec<String>> {
+    let mut table: toml::value::Table = toml::from_str(s)?;
+    let profiles = table.remove("profile");
+
+    // 先合并 profile 和顶层的配置，profile 中的值（包括 false）覆盖顶层的值
+    let mut merged = normalize(&table)?;
+    if let Some(name) = profile {
+        let profile = profiles
+            .as_ref()
+            .and_then(|p| p.get(name))
+            .and_then(Value::as_table)
+            .with_context(|| format!("配置文件中没有 [profile.{name}]"))?;
+        merged.extend(normalize(profile)?);
+    }
+    table_to_args(&merged, bool_flags)
+}
+
+/// 统一 key 为命令行参数的形式，如 `font_size` 转为 `font-size`
+fn normalize(table: &toml::value::Table) -> Result<toml::value::Table> {
+    let mut out = toml::value::Table::new();
+    for (key, value) in table {
+        let flag = key.replace('_', "-");
+        if RESERVED_KEYS.contains(&flag.as_str()) {
+            bail!("配置文件中不能指定 {key}");
+        }
+        out.insert(flag, value.clone());
+    }
+    Ok(out)
+}
+
+fn table_to_args(table: &toml::value::Table, bool_flags: &[String]) -> Result<Vec<String>> {
+    let is_bool = |name: &str| bool_flags.iter().any(|f| f == name);
+    let mut args = vec![];
+    for (flag, value) in table {
+        // 使用 --key=value 的形式，避免负数被当作参数
+        match value {
+            Value::Boolean(_) if !is_bool(flag) => bail!("配置项 {flag} 需要数值"),
+            Value::Boolean(true) => args.push(format!("--{flag}")),
+            Value::Boolean(false) => {}
+            Value::Integer(i) => args.push(format!("--{flag}={i}")),
+            Value::Float(f) => args.push(format!("--{flag}={f}")),
+            Value::String(s) => args.push(format!("--{flag}={s}")),
+            Value::Array(_) => bail!("配置项 {flag} 不支持数组，多个值请写成逗号分隔的字符串"),
+            _ => bail!("配置项 {flag} 的类型不支持"),
+        }
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bool_flags() -> Vec<String> {
+        crate::Args::bool_flags()
+    }
+
+    static CONFIG: &str = r#"
+        font_size = 36
+        time-offset = -1.5
+        bold = true
+        keep_fixed = false
+        font = "微软雅黑"
+
+        [profile.1080p]
+        width = 1920
+        height = 1080
+    "#;
+
+    #[test]
+    fn to_args() {
+        let args = args_from_str(CONFIG, None, &bool_flags()).unwrap();
+        assert_eq!(
+            args,
+            [
+                "--bold",
+                "--font=微软雅黑",
+                "--font-size=36",
+                "--time-offset=-1.5"
+            ]
+        );
+
+        let args = args_from_str(CONFIG, Some("1080p"), &bool_flags()).unwrap();
+        assert_eq!(
+            args,
+            [
+                "--bold",
+                "--font=微软雅黑",
+                "--font-size=36",
+                "--height=1080",
+                "--time-offset=-1.5",
+                "--width=1920"
+            ]
+        );
+        assert!(args_from_str("pools = ['normal']", None, &bool_flags()).is_err());
+
+        assert!(args_from_str(CONFIG, Some("mobile"), &bool_flags()).is_err());
+        assert!(args_from_str("input = 'a.xml'", None, &bool_flags()).is_err());
+        // 开关以外的参数不能写成 true/false
+        let err = args_from_str("outline = false", None, &bool_flags()).unwrap_err();
+        assert_eq!(err.to_string(), "配置项 outline 需要数值");
+        assert!(args_from_str("outline = true", None, &bool_flags()).is_err());
+
+        let args = args_from_str("sessdata = 'secret'\nbold = true", None, &bool_flags()).unwrap();
+        assert_eq!(redacted(&args), ["--bold", "--sessdata=***"]);
+    }
+
+    #[test]
+    fn override_file_values() {
+        use clap::Parser;
+
+        static CONFIG: &str = r#"
+            bold = true
+            keep_fixed = true
+            font-size = 36
+
+            [profile.plain]
+            bold = false
+            font_size = 25
+        "#;
+        // profile 覆盖顶层的配置，包括关闭开关
+        let args = args_from_str(CONFIG, Some("plain"), &bool_flags()).unwrap();
+        assert_eq!(args, ["--font-size=25", "--keep-fixed"]);
+
+        // 命令行参数覆盖配置文件
+        let bool_flags = ["bold".to_string(), "keep-fixed".to_string()];
+        let file_args = args_from_str(CONFIG, None, &bool_flags).unwrap();
+        let cli = |args: &[&str]| -> Vec<OsString> {
+            std::iter::once("danmu2ass")
+                .chain(args.iter().copied())
+                .map(OsString::from)
+                .collect()
+        };
+        let merged = merge_args(
+            cli(&["--no-keep-fixed", "--font-size=30", "a.xml"]),
+            file_args.clone(),
+            &bool_flags,
+        );
+        assert_eq!(
+            merged,
+            cli(&["--bold", "--font-size=36", "--font-size=30", "a.xml"])
+        );
+        let merged = merge_args(cli(&["--bold=false"]), file_args, &bool_flags);
+        assert_eq!(merged, cli(&["--font-size=36", "--keep-fixed"]));
+
+        let args = crate::Args::try_parse_from(merge_args(
+            cli(&["--no-bold"]),
+            args_from_str(CONFIG, None, &bool_flags).unwrap(),
+            &crate::Args::bool_flags(),
+        ))
+        .unwrap();
+        assert!(!args.bold);
+    }
+}
@@ -1,13 +1,64 @@
 //! 一个弹幕实例，但是没有位置信息
 use super::CanvasConfig;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub enum DanmuType {
     #[default]
     Float,
     Top,
     Bottom,
     Reverse,
+    /// 高级弹幕（mode 7），content 为 JSON 格式的参数，绘制时解析
+    Advanced,
+    /// 互动弹幕（UP 主弹幕、投票、关联视频等），绘制为卡片，content 为卡片内容
+    Command {
+        /// 卡片的标题，如“投票”
+        title: String,
+        /// 显示时间，单位为毫秒
+        duration_ms: u32,
+    },
+    /// 直播的醒目留言，content 为留言内容
+    SuperChat {
+        user: String,
+        /// 价格，单位为元
+        price: u32,
+    },
+    /// 直播礼物
+    Gift {
+        user: String,
+        gift_name: String,
+        count: u32,
+    },
+    /// 直播上舰，level 1 为总督，2 为提督，3 为舰长，count 为月数
+    Guard {
+        user: String,
+        level: u32,
+        count: u32,
+    },
+}
+
+/// 哔哩哔哩视频的弹幕池
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Pool {
+    /// 普通弹幕（0）
+    #[default]
+    Normal,
+    /// 字幕弹幕（1），绘制为底部居中的字幕
+    Subtitle,
+    /// 特殊弹幕（2），如高级弹幕
+    Special,
+}
+
+impl Pool {
+    /// 不认识的弹幕池视为普通弹幕
+    pub fn from_num(num: u32) -> Self {
+        match num {
+            1 => Pool::Subtitle,
+            2 => Pool::Special,
+            _ => Pool::Normal,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -15,25 +66,45 @@ pub struct Danmu {
     pub timeline_s: f64,
     pub content: String,
     pub r#type: DanmuType,
-    /// 虽然这里有 fontsize，但是我们实际上使用 canvas config 的 font size，
-    /// 否在在调节分辨率的时候字体会发生变化。
+    /// 弹幕原本的字号，默认为 25。绘制时使用 canvas config 的 font size，
+    /// 否则在调节分辨率的时候字体会发生变化；开启 `source_font_size` 时按相对于 25 的比例缩放
     pub fontsize: u32,
     pub rgb: (u8, u8, u8),
+    /// 发送者，录播姬的 XML 中为 UID，哔哩哔哩视频弹幕中为 mid_hash
+    pub sender: Option<String>,
+    /// 哔哩哔哩视频弹幕的权重（0 到 10），用于智能屏蔽，录播姬的 XML 等没有权重时为 None
+    pub weight: Option<u32>,
+    /// 弹幕池，录播姬的 XML 和直播中都是普通弹幕
+    pub pool: Pool,
+    /// 相对于 canvas config 字号的缩放，None 为不缩放
+    pub font_scale: Option<f64>,
 }
 
 impl Danmu {
-    /// 计算弹幕的“像素长度”，会乘上一个缩放因子
+    /// 计算弹幕的“像素长度”
     ///
-    /// 汉字算一个全宽，英文算2/3宽
+    /// 如果指定了字体文件，使用字体中的字形宽度计算；
+    /// 否则汉字算一个全宽，英文算2/3宽，并乘上一个缩放因子
     pub fn length(&self, config: &CanvasConfig) -> f64 {
-        let pts = config.font_size
-            * self
-                .content
-                .chars()
-                .map(|ch| if ch.is_ascii() { 2 } else { 3 })
-                .sum::<u32>()
-            / 3;
+        text_length(&self.content, config) * self.font_scale.unwrap_or(1.0)
+    }
+}
 
-        pts as f64 * config.width_ratio
+/// 计算一段文本的“像素长度”，见 [`Danmu::length`]
+pub fn text_length(text: &str, config: &CanvasConfig) -> f64 {
+    if let Some(metrics) = config.font_metrics.as_ref() {
+        // 字体中没有的字符按照汉字全宽、英文 2/3 宽估计
+        return metrics.text_width(text, config.font_size as f64, |ch| {
+            let pts = if ch.is_ascii() { 2.0 } else { 3.0 };
+            config.font_size as f64 * pts / 3.0 * config.width_ratio
+        });
     }
+    let pts = config.font_size
+        * text
+            .chars()
+            .map(|ch| if ch.is_ascii() { 2 } else { 3 })
+            .sum::<u32>()
+        / 3;
+
+    pts as f64 * config.width_ratio
 }
@@ -0,0 +1,214 @@
+//! 合并刷屏弹幕：时间窗口内相同或相近的弹幕合并为一条“内容 ×N”
+use crate::{danmu::DanmuType, Danmu};
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+
+struct Group {
+    danmu: Danmu,
+    /// 归一化之后的内容，None 表示不参与合并
+    key: Option<String>,
+    count: usize,
+}
+
+/// 输出保持输入的时间顺序，每条弹幕在时间窗口结束之后才会输出
+pub struct Dedup<I> {
+    inner: I,
+    /// 从第一条弹幕开始计算的时间窗口
+    window: f64,
+    /// 归一化之后的编辑距离相似度，1 代表只合并归一化之后完全相同的弹幕
+    similarity: f64,
+    /// 合并之后按数量放大字号，最多放大到这个倍数，None 代表不放大
+    max_scale: Option<f64>,
+    pending: VecDeque<Group>,
+    /// 第一个 pending 的序号
+    front_seq: usize,
+    /// 归一化内容 => 序号
+    index: HashMap<String, usize>,
+    latest: f64,
+    finished: bool,
+}
+
+impl<I> Dedup<I>
+where
+    I: Iterator<Item = Result<Danmu>>,
+{
+    pub fn new(inner: I, window: f64, similarity: f64, max_scale: Option<f64>) -> Self {
+        Self {
+            inner,
+            window,
+            similarity,
+            max_scale,
+            pending: VecDeque::new(),
+            front_seq: 0,
+            index: HashMap::new(),
+            latest: f64::MIN,
+            finished: false,
+        }
+    }
+
+    fn push(&mut self, danmu: Danmu) {
+        let key = match danmu.r#type {
+            DanmuType::Float | DanmuType::Top | DanmuType::Bottom | DanmuType::Reverse => {
+                Some(normalize(&danmu.content)).filter(|k| !k.is_empty())
+            }
+            _ => None,
+        };
+        if let Some(key) = key.as_ref() {
+            if let Some(group) = self.find(key, danmu.timeline_s) {
+                group.count += 1;
+                return;
+            }
+            self.index
+                .insert(key.clone(), self.front_seq + self.pending.len());
+        }
+        self.pending.push_back(Group {
+            danmu,
+            key,
+            count: 1,
+        });
+    }
+
+    fn find(&mut self, key: &str, timeline_s: f64) -> Option<&mut Group> {
+        let window = self.window;
+        let in_window = move |g: &Group| g.danmu.timeline_s + window >= timeline_s;
+        if let Some(&seq) = self.index.get(key) {
+            let group = &self.pending[seq - self.front_seq];
+            if in_window(group) {
+                return self.pending.get_mut(seq - self.front_seq);
+            }
+        }
+        if self.similarity >= 1.0 {
+            return None;
+        }
+        let len = key.chars().count();
+        let similarity = self.similarity;
+        self.pending.iter_mut().find(|g| {
+            let Some(k) = g.key.as_deref() else {
+                return false;
+            };
+            // 长度相差太多时不可能相似，跳过编辑距离的计算
+            let k_len = k.chars().count();
+            let max_len = len.max(k_len) as f64;
+            (len.abs_diff(k_len) as f64) <= (1.0 - similarity) * max_len
+                && in_window(g)
+                && strsim::normalized_levenshtein(k, key) >= similarity
+        })
+    }
+
+    fn pop(&mut self) -> Option<Danmu> {
+        let group = self.pending.pop_front()?;
+        if let Some(key) = group.key.as_ref() {
+            if self.index.get(key) == Some(&self.front_seq) {
+                self.index.remove(key);
+            }
+        }
+        self.front_seq += 1;
+
+        let mut danmu = group.danmu;
+        if group.count > 1 {
+            danmu.content = format!("{} ×{}", danmu.content.trim(), group.count);
+            if let Some(max_scale) = self.max_scale {
+                let scale = (1.0 + (group.count as f64).log2() / 4.0).min(max_scale);
+                if scale > 1.0 {
+                    danmu.font_scale = Some(scale);
+                }
+            }
+        }
+        Some(danmu)
+    }
+}
+
+impl<I> Iterator for Dedup<I>
+where
+    I: Iterator<Item = Result<Danmu>>,
+{
+    type Item = Result<Danmu>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(front) = self.pending.front() {
+                if self.finished || front.danmu.timeline_s + self.window < self.latest {
+                    return self.pop().map(Ok);
+                }
+            }
+            if self.finished {
+                return None;
+            }
+            match self.inner.next() {
+                Some(Ok(danmu)) => {
+                    self.latest = self.latest.max(danmu.timeline_s);
+                    self.push(danmu);
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => self.finished = true,
+            }
+        }
+    }
+}
+
+/// 去掉空白、全角转半角、英文转小写，并将连续重复的字符压缩为一个
+fn normalize(text: &str) -> String {
+    let mut key = String::with_capacity(text.len());
+    let mut last = None;
+    for ch in text.chars().filter(|c| !c.is_whitespace()) {
+        let ch = match ch {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(ch as u32 - 0xFEE0).unwrap_or(ch),
+            _ => ch,
+        }
+        .to_ascii_lowercase();
+        if last != Some(ch) {
+            key.push(ch);
+            last = Some(ch);
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn danmu(timeline_s: f64, content: &str) -> Result<Danmu> {
+        Ok(Danmu {
+            timeline_s,
+            content: content.to_string(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn normalize_content() {
+        assert_eq!(normalize("哈哈哈哈"), "哈");
+        assert_eq!(normalize(" 哈 哈 "), "哈");
+        assert_eq!(normalize("？？？"), "?");
+        assert_eq!(normalize("AWSL"), "awsl");
+        assert_eq!(normalize("2333333"), "23");
+    }
+
+    #[test]
+    fn merge_spam() {
+        let input = vec![
+            danmu(0.0, "哈哈哈"),
+            danmu(1.0, "主播好"),
+            danmu(2.0, "哈哈哈哈哈"),
+            danmu(3.0, "主播你好"),
+            danmu(4.0, "哈 哈"),
+            // 超出时间窗口
+            danmu(6.0, "哈哈"),
+        ];
+        let output = Dedup::new(input.into_iter(), 5.0, 0.7, Some(1.28))
+            .map(|d| {
+                let d = d.unwrap();
+                (d.timeline_s, d.content, d.font_scale)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            output,
+            vec![
+                (0.0, "哈哈哈 ×3".to_string(), Some(1.28)),
+                (1.0, "主播好 ×2".to_string(), Some(1.25)),
+                (6.0, "哈哈".to_string(), None),
+            ]
+        );
+    }
+}
@@ -23,6 +23,32 @@ impl Drawable {
 }
 
 pub enum DrawEffect {
-    Move { start: (i32, i32), end: (i32, i32) },
-    Fixed {},
+    Move {
+        start: (i32, i32),
+        end: (i32, i32),
+    },
+    /// 带背景框的固定位置，pos 为背景框左上角，文字从内边距之后开始绘制
+    Boxed {
+        pos: (i32, i32),
+        size: (i32, i32),
+        padding: i32,
+        background: (u8, u8, u8),
+    },
+    /// 固定位置，坐标的含义由 style 的 alignment 决定
+    Fixed {
+        pos: (i32, i32),
+    },
+    /// 高级弹幕，从 start 移动到 end，坐标为文字左上角
+    Advanced {
+        start: (i32, i32),
+        end: (i32, i32),
+        /// 移动的开始和结束时间，相对于弹幕出现的时间，单位为毫秒
+        move_time: (u32, u32),
+        /// 开始和结束时的不透明度，0 为透明，1 为不透明
+        alpha: (f64, f64),
+        /// Z 轴和 Y 轴的旋转角度，和哔哩哔哩一致，Z 轴为顺时针
+        rotate: (f64, f64),
+        stroke: bool,
+        font: Option<String>,
+    },
 }
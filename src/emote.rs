@@ -0,0 +1,175 @@
+//! 弹幕中的表情，如 `[doge]`，替换为可读的文字
+//!
+//! 表情文件每行一条，空行和 `#` 开头的行会被忽略，会覆盖内置的替换：
+//!
+//! ```text
+//! [doge] => (狗头)
+//! # {url} 会被替换为视频弹幕元数据中的表情图片地址，没有地址时保留原文
+//! [热词系列_知识增加] => [图片 {url}]
+//! ```
+use crate::bilibili::Expression;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+/// 内置的常用表情替换
+const BUILTIN: &[(&str, &str)] = &[
+    ("[doge]", "🐶"),
+    ("[笑哭]", "😂"),
+    ("[妙啊]", "👍"),
+    ("[点赞]", "👍"),
+    ("[OK]", "👌"),
+    ("[星星眼]", "🤩"),
+    ("[喜欢]", "😍"),
+    ("[大哭]", "😭"),
+    ("[捂脸]", "🤦"),
+    ("[吃瓜]", "🍉"),
+    ("[微笑]", "🙂"),
+    ("[呲牙]", "😁"),
+    ("[偷笑]", "🤭"),
+    ("[害羞]", "😳"),
+    ("[惊讶]", "😲"),
+    ("[生气]", "😠"),
+    ("[思考]", "🤔"),
+    ("[疑惑]", "🤨"),
+    ("[滑稽]", "😏"),
+    ("[酸了]", "🍋"),
+    ("[藏狐]", "🦊"),
+    ("[辣眼睛]", "🙈"),
+    ("[吐]", "🤮"),
+    ("[墨镜]", "😎"),
+    ("[再见]", "👋"),
+    ("[打call]", "📣"),
+    ("[爱心]", "❤"),
+];
+
+#[derive(Debug, Clone)]
+pub struct Emotes {
+    replacements: HashMap<String, String>,
+    /// 视频弹幕元数据中的表情图片地址
+    urls: HashMap<String, String>,
+}
+
+impl Default for Emotes {
+    fn default() -> Self {
+        Self {
+            replacements: BUILTIN
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            urls: HashMap::new(),
+        }
+    }
+}
+
+impl Emotes {
+    /// 在内置替换的基础上解析表情文件
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut emotes = Self::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, replacement) = line
+                .split_once(" => ")
+                .with_context(|| format!("第 {} 行：缺少 \" => \"", idx + 1))?;
+            let keyword = keyword.trim();
+            if !(keyword.starts_with('[') && keyword.ends_with(']')) {
+                anyhow::bail!("第 {} 行：表情 {} 需要用 [] 括起来", idx + 1, keyword);
+            }
+            emotes
+                .replacements
+                .insert(keyword.to_string(), replacement.trim().to_string());
+        }
+        Ok(emotes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.replacements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replacements.is_empty()
+    }
+
+    /// 加上视频的表情图片地址
+    pub fn with_expressions(&self, expressions: &[Expression]) -> Self {
+        let mut emotes = self.clone();
+        for e in expressions.iter().filter(|e| !e.url.is_empty()) {
+            for keyword in &e.keyword {
+                emotes.urls.insert(keyword.clone(), e.url.clone());
+            }
+        }
+        emotes
+    }
+
+    /// 替换文本中的表情，没有需要替换的表情时返回 `None`
+    pub fn replace(&self, text: &str) -> Option<String> {
+        let mut out = String::new();
+        let mut rest = text;
+        let mut replaced = false;
+        while let Some(start) = rest.find('[') {
+            let Some(len) = rest[start..].find(']') else {
+                break;
+            };
+            let keyword = &rest[start..start + len + 1];
+            match self.lookup(keyword) {
+                Some(replacement) => {
+                    out.push_str(&rest[..start]);
+                    out.push_str(&replacement);
+                    rest = &rest[start + len + 1..];
+                    replaced = true;
+                }
+                // 表情中不会有 [，从下一个 [ 开始继续查找
+                None => {
+                    let next = start + 1;
+                    out.push_str(&rest[..next]);
+                    rest = &rest[next..];
+                }
+            }
+        }
+        out.push_str(rest);
+        replaced.then_some(out)
+    }
+
+    fn lookup(&self, keyword: &str) -> Option<String> {
+        let replacement = self.replacements.get(keyword)?;
+        if !replacement.contains("{url}") {
+            return Some(replacement.clone());
+        }
+        let url = self.urls.get(keyword)?;
+        Some(replacement.replace("{url}", url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace() {
+        let emotes =
+            Emotes::parse("# 注释\n[doge] => (狗头)\n[热词系列_知识增加] => [图片 {url}]\n")
+                .unwrap();
+        assert_eq!(
+            emotes.replace("[[doge]][笑哭]x[未知]"),
+            Some("[(狗头)]😂x[未知]".to_string())
+        );
+        assert_eq!(emotes.replace("没有表情[doge"), None);
+        // 没有图片地址时保留原文
+        assert_eq!(emotes.replace("[热词系列_知识增加]"), None);
+
+        let emotes = emotes.with_expressions(&[Expression {
+            keyword: vec!["[热词系列_知识增加]".to_string()],
+            url: "https://i0.hdslb.com/a.png".to_string(),
+            period: vec![],
+        }]);
+        assert_eq!(
+            emotes.replace("[热词系列_知识增加]").unwrap(),
+            "[图片 https://i0.hdslb.com/a.png]"
+        );
+
+        assert!(Emotes::parse("[doge]").is_err());
+        assert!(Emotes::parse("doge => 狗").is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! 输入文件的格式：录播姬等的 XML、哔哩哔哩的 protobuf 分段以及 JSON 格式的弹幕存档
+use crate::bilibili::{DanmakuElem, DmSegMobileReply};
+use crate::{Danmu, Parser};
+use anyhow::{Context, Result};
+use prost::Message;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Xml,
+    /// `DmSegMobileReply`，如哔哩哔哩接口返回的 `.so` 分段
+    Protobuf,
+    /// `DanmakuElem` 的数组，或者 `{"elems": [...]}`
+    Json,
+}
+
+pub type DanmuIter = Box<dyn Iterator<Item = Result<Danmu>>>;
+
+impl FileFormat {
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "xml" => Some(FileFormat::Xml),
+            "pb" | "so" => Some(FileFormat::Protobuf),
+            "json" => Some(FileFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// 根据文件开头的内容判断格式
+    pub fn sniff(head: &[u8]) -> Self {
+        let head = head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head);
+        match head.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'<') => FileFormat::Xml,
+            Some(b'[' | b'{') => FileFormat::Json,
+            _ => FileFormat::Protobuf,
+        }
+    }
+
+    /// 优先根据扩展名判断格式，无法判断时读取文件开头
+    pub fn detect(path: &Path) -> Result<Self> {
+        if let Some(format) = Self::from_extension(path) {
+            return Ok(format);
+        }
+        let mut head = [0u8; 64];
+        let mut file = std::fs::File::open(path)?;
+        let n = file.read(&mut head)?;
+        let format = Self::sniff(&head[..n]);
+        debug!("文件 {} 识别为 {:?}", path.display(), format);
+        Ok(format)
+    }
+
+    /// 判断文件是否为弹幕文件，用于跳过文件夹中的其他文件
+    ///
+    /// JSON 需要是数组或者带有 `elems` 的对象，避免把录播姬的 `config.json` 之类的文件当作弹幕；
+    /// protobuf 需要至少解析出一条正常的弹幕，避免把动态链接库之类的 `.so` 文件当作弹幕
+    pub fn probe(path: &Path) -> Result<Option<Self>> {
+        let format = Self::from_extension(path);
+        let is_danmaku = match format {
+            Some(FileFormat::Json) => {
+                let content = std::fs::read(path)?;
+                let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&content);
+                match content.iter().find(|b| !b.is_ascii_whitespace()) {
+                    Some(b'[') => true,
+                    Some(b'{') => serde_json::from_slice::<
+                        std::collections::HashMap<String, serde::de::IgnoredAny>,
+                    >(content)
+                    .map(|obj| obj.contains_key("elems"))
+                    .unwrap_or(false),
+                    _ => false,
+                }
+            }
+            Some(FileFormat::Protobuf) => {
+                let content = std::fs::read(path)?;
+                DmSegMobileReply::decode(content.as_slice())
+                    .map(|reply| {
+                        reply.elems.iter().any(|e| {
+                            e.progress >= 0 && (1..=9).contains(&e.mode) && !e.content.is_empty()
+                        })
+                    })
+                    .unwrap_or(false)
+            }
+            _ => true,
+        };
+        Ok(format.filter(|_| is_danmaku))
+    }
+
+    /// 递归查找文件夹下的所有弹幕文件
+    pub fn find_files(folder: &Path) -> Result<Vec<PathBuf>> {
+        let glob = format!("{}/**/*", folder.display());
+        let mut targets = vec![];
+        for path in glob::glob(&glob)? {
+            let path = path?;
+            if !path.is_file() {
+                continue;
+            }
+            match Self::probe(&path) {
+                Ok(Some(_)) => targets.push(path),
+                Ok(None) => {}
+                Err(e) => debug!("跳过文件 {}：{:?}", path.display(), e),
+            }
+        }
+        Ok(targets)
+    }
+
+    /// 打开文件，XML 流式解析，其他格式一次性读入
+    pub fn open(&self, path: &Path, parse_gifts: bool) -> Result<DanmuIter> {
+        match self {
+            FileFormat::Xml => Ok(Box::new(Parser::from_path(path)?.parse_gifts(parse_gifts))),
+            FileFormat::Protobuf | FileFormat::Json => {
+                let content = std::fs::read(path)?;
+                let elems = self.decode(&content)?;
+                Ok(Box::new(elems.into_iter().map(|e| Ok(e.into()))))
+            }
+        }
+    }
+
+    fn decode(&self, content: &[u8]) -> Result<Vec<DanmakuElem>> {
+        match self {
+            FileFormat::Protobuf => Ok(DmSegMobileReply::decode(content)
+                .context("无法解析为 DmSegMobileReply")?
+                .into_elems()),
+            FileFormat::Json => {
+                #[derive(serde::Deserialize)]
+                #[serde(untagged)]
+                enum Archive {
+                    Elems(Vec<DanmakuElem>),
+                    Reply(DmSegMobileReply),
+                }
+                let archive: Archive =
+                    serde_json::from_slice(content).context("无法解析为弹幕 JSON")?;
+                Ok(match archive {
+                    Archive::Elems(elems) => elems,
+                    Archive::Reply(reply) => reply.into_elems(),
+                })
+            }
+            FileFormat::Xml => unreachable!("XML 使用 Parser 解析"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bilibili::{DanmakuAIFlag, DanmakuFlag};
+
+    #[test]
+    fn sniff() {
+        assert_eq!(
+            FileFormat::sniff(b"\xEF\xBB\xBF<?xml version"),
+            FileFormat::Xml
+        );
+        assert_eq!(FileFormat::sniff(b"\n  [{\"id\": 1}]"), FileFormat::Json);
+        assert_eq!(FileFormat::sniff(&[0x0a, 0x2c, 0x08]), FileFormat::Protobuf);
+    }
+
+    #[test]
+    fn find_files() {
+        let folder = std::env::temp_dir().join(format!("danmu2ass-find-{}", std::process::id()));
+        std::fs::create_dir_all(folder.join("sub")).unwrap();
+        for (name, content) in [
+            ("a.xml", "<i></i>"),
+            ("sub/b.json", "[]"),
+            ("c.json", r#"{"elems": []}"#),
+            // 录播姬的配置文件
+            ("config.json", r#"{"$schema": "...", "version": "3"}"#),
+            ("d.txt", "弹幕"),
+        ] {
+            std::fs::write(folder.join(name), content).unwrap();
+        }
+        let segment = DmSegMobileReply {
+            elems: vec![DanmakuElem {
+                progress: 1500,
+                mode: 1,
+                content: "弹幕".to_string(),
+                ..Default::default()
+            }],
+            ai_flag: None,
+        };
+        std::fs::write(folder.join("seg.so"), segment.encode_to_vec()).unwrap();
+        // 动态链接库不是弹幕，空的分段也跳过
+        std::fs::write(folder.join("lib.so"), b"\x7FELF\x02\x01\x01\x00").unwrap();
+        std::fs::write(folder.join("empty.pb"), b"").unwrap();
+        let mut files = FileFormat::find_files(&folder).unwrap();
+        files.sort();
+        std::fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(
+            files,
+            [
+                folder.join("a.xml"),
+                folder.join("c.json"),
+                folder.join("seg.so"),
+                folder.join("sub/b.json")
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_archives() {
+        let elem = DanmakuElem {
+            id: 123,
+            progress: 1500,
+            mode: 1,
+            fontsize: 25,
+            color: 0xFF0000,
+            mid_hash: "1537d7c7".to_string(),
+            content: "弹幕".to_string(),
+            ..Default::default()
+        };
+        // 有 AI 评分的弹幕使用评分作为权重
+        let pb = DmSegMobileReply {
+            elems: vec![elem.clone()],
+            ai_flag: Some(DanmakuAIFlag {
+                dm_flags: vec![DanmakuFlag { dmid: 123, flag: 7 }],
+            }),
+        }
+        .encode_to_vec();
+        let from_pb = FileFormat::Protobuf.decode(&pb).unwrap();
+        assert_eq!(from_pb[0].weight, 7);
+        // protobuf 中没有 AI 评分的权重 0 也是权重
+        let zero = DmSegMobileReply {
+            elems: vec![elem.clone()],
+            ai_flag: None,
+        }
+        .encode_to_vec();
+        let zero: Danmu = FileFormat::Protobuf.decode(&zero).unwrap()[0]
+            .clone()
+            .into();
+        assert_eq!(zero.weight, Some(0));
+
+        // protobuf 的 JSON 映射中 int64 为字符串，字段为驼峰命名
+        let json = r#"[{"id": "123", "progress": 1500, "mode": 1, "fontsize": 25,
+            "color": 16711680, "midHash": "1537d7c7", "content": "弹幕"}]"#;
+        let from_json = FileFormat::Json.decode(json.as_bytes()).unwrap();
+        let from_reply = FileFormat::Json
+            .decode(r#"{"elems": [{"id": 123, "progress": 1500, "content": "弹幕"}]}"#.as_bytes())
+            .unwrap();
+
+        let config = crate::CanvasConfig {
+            ai_level: 5,
+            ..crate::canvas::tests::config()
+        };
+        for (elems, weight) in [(from_pb, Some(7)), (from_json, None), (from_reply, None)] {
+            assert_eq!(elems.len(), 1);
+            assert_eq!(elems[0].id, 123);
+            let danmu: Danmu = elems[0].clone().into();
+            assert_eq!(danmu.timeline_s, 1.5);
+            assert_eq!(danmu.weight, weight);
+            assert_eq!(danmu.content, "弹幕");
+            // 没有权重的 JSON 弹幕不受智能屏蔽影响
+            assert!(config.accepts(&danmu));
+        }
+
+        let weighted = FileFormat::Json
+            .decode(r#"[{"id": 1, "weight": 0}, {"id": 2, "weight": "3"}]"#.as_bytes())
+            .unwrap();
+        let weights: Vec<_> = weighted
+            .into_iter()
+            .map(|e| Danmu::from(e).weight)
+            .collect();
+        assert_eq!(weights, [Some(0), Some(3)]);
+    }
+}
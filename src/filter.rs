@@ -0,0 +1,204 @@
+//! 弹幕过滤规则
+//!
+//! 规则文件每行一条规则，空行、`//` 开头的行和 `# ` 开头（`#` 后面跟空格）的行为注释。
+//! `#原神` 这样 `#` 后面直接跟文字的行是关键词：
+//!
+//! ```text
+//! # 包含关键词
+//! 关键词
+//! eq:完全一致的弹幕
+//! re:^哈{5,}$
+//! # 同时匹配哔哩哔哩视频弹幕中该用户的 mid_hash
+//! uid:398452452
+//! hash:1537d7c7
+//! re:^awsl$ => #FF0000
+//! ```
+//!
+//! 行尾的 ` => #RRGGBB` 将匹配的弹幕改为指定颜色，默认（或者 ` => drop`）丢弃弹幕。
+//! 一条弹幕匹配多条规则时，使用最靠前的规则。
+use crate::Danmu;
+use anyhow::{bail, Context, Result};
+use regex::RegexSet;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Drop,
+    Recolor((u8, u8, u8)),
+}
+
+/// (规则序号, 动作)
+type Rule = (usize, Action);
+
+#[derive(Debug, Clone)]
+pub struct Filter {
+    /// 所有内容规则编译为一个自动机，下标和 `content_rules` 对应
+    contents: RegexSet,
+    content_rules: Vec<Rule>,
+    senders: HashMap<String, Rule>,
+    len: usize,
+}
+
+impl Filter {
+    pub fn new<'a>(lines: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let mut patterns = vec![];
+        let mut content_rules = vec![];
+        let mut senders = HashMap::new();
+        let mut len = 0;
+        for (idx, line) in lines.into_iter().enumerate() {
+            let line = line.trim();
+            if is_comment(line) {
+                continue;
+            }
+            let (rule, action) =
+                parse_action(line).with_context(|| format!("第 {} 行", idx + 1))?;
+            len += 1;
+            if let Some(uid) = rule.strip_prefix("uid:") {
+                let uid = uid.trim();
+                let mid: u64 = uid
+                    .parse()
+                    .with_context(|| format!("第 {} 行：UID 解析错误", idx + 1))?;
+                senders.entry(uid.to_string()).or_insert((idx, action));
+                senders.entry(mid_hash(mid)).or_insert((idx, action));
+            } else if let Some(hash) = rule.strip_prefix("hash:") {
+                senders
+                    .entry(hash.trim().to_lowercase())
+                    .or_insert((idx, action));
+            } else {
+                let pattern = if let Some(re) = rule.strip_prefix("re:") {
+                    re.to_string()
+                } else if let Some(exact) = rule.strip_prefix("eq:") {
+                    format!("^{}$", regex::escape(exact))
+                } else {
+                    regex::escape(rule)
+                };
+                patterns.push(pattern);
+                content_rules.push((idx, action));
+            }
+        }
+        let contents = RegexSet::new(&patterns).context("正则表达式编译错误")?;
+        Ok(Self {
+            contents,
+            content_rules,
+            senders,
+            len,
+        })
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        Self::new(text.lines())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 对弹幕应用规则，返回 false 表示弹幕需要丢弃
+    pub fn apply(&self, danmu: &mut Danmu) -> bool {
+        let by_sender = danmu
+            .sender
+            .as_ref()
+            .and_then(|s| self.senders.get(s.as_str()));
+        // 序号最小的规则优先
+        let by_content = self
+            .contents
+            .matches(danmu.content.trim())
+            .iter()
+            .next()
+            .map(|i| &self.content_rules[i])
+            .filter(|(idx, _)| by_sender.is_none_or(|(s, _)| idx < s));
+        match by_content.or(by_sender) {
+            None => true,
+            Some((_, Action::Drop)) => false,
+            Some((_, Action::Recolor(rgb))) => {
+                danmu.rgb = *rgb;
+                true
+            }
+        }
+    }
+}
+
+/// 空行、`//` 开头和 `#` 后面跟空白（或只有 `#`）的行为注释，`#原神` 这样的话题是关键词
+fn is_comment(line: &str) -> bool {
+    line.is_empty()
+        || line.starts_with("//")
+        || line
+            .strip_prefix('#')
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+fn parse_action(line: &str) -> Result<(&str, Action)> {
+    let Some((rule, action)) = line.rsplit_once(" => ") else {
+        return Ok((line, Action::Drop));
+    };
+    let action = action.trim();
+    if action == "drop" {
+        return Ok((rule.trim_end(), Action::Drop));
+    }
+    let Some(hex) = action.strip_prefix('#').filter(|h| h.len() == 6) else {
+        bail!("无法识别的动作 {action}，应为 drop 或 #RRGGBB");
+    };
+    let rgb = u32::from_str_radix(hex, 16).with_context(|| format!("颜色解析错误：{action}"))?;
+    Ok((
+        rule.trim_end(),
+        Action::Recolor(((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)),
+    ))
+}
+
+/// 哔哩哔哩视频弹幕中的 mid_hash 为 UID 的 CRC32
+fn mid_hash(mid: u64) -> String {
+    format!("{:x}", crc32fast::hash(mid.to_string().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn danmu(content: &str, sender: &str) -> Danmu {
+        Danmu {
+            content: content.to_string(),
+            rgb: (0xFF, 0xFF, 0xFF),
+            sender: Some(sender.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn apply_rules() {
+        let filter = Filter::parse(
+            "# 注释\n\
+             // 注释\n\
+             #\n\
+             关键词\n\
+             #原神\n\
+             eq:哈哈\n\
+             re:^a+wsl$ => #FF0000\n\
+             uid:123 => #00FF00\n\
+             hash:ABCDEF\n",
+        )
+        .unwrap();
+        let apply = |mut d: Danmu| filter.apply(&mut d).then_some(d.rgb);
+        assert_eq!(apply(danmu("包含关键词的弹幕", "1")), None);
+        assert_eq!(apply(danmu("哈哈", "1")), None);
+        assert_eq!(apply(danmu("哈哈哈", "1")), Some((0xFF, 0xFF, 0xFF)));
+        assert_eq!(apply(danmu("aaawsl", "1")), Some((0xFF, 0, 0)));
+        assert_eq!(apply(danmu("普通弹幕", "123")), Some((0, 0xFF, 0)));
+        assert_eq!(apply(danmu("普通弹幕", &mid_hash(123))), Some((0, 0xFF, 0)));
+        assert_eq!(apply(danmu("普通弹幕", "abcdef")), None);
+        // 关键词规则在 uid 规则之前
+        assert_eq!(apply(danmu("关键词", "123")), None);
+        // # 后面直接跟文字的是话题关键词，不是注释
+        assert_eq!(apply(danmu("一起玩 #原神", "1")), None);
+        assert_eq!(apply(danmu("注释", "1")), Some((0xFF, 0xFF, 0xFF)));
+    }
+
+    #[test]
+    fn invalid_action() {
+        assert!(Filter::parse("awsl => red").is_err());
+        assert!(Filter::parse("re:(").is_err());
+    }
+}
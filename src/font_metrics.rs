@@ -0,0 +1,70 @@
+//! 从 TTF/OTF 字体文件中读取字形宽度，用于准确计算弹幕长度
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont};
+use anyhow::{Context, Result};
+use std::{fmt, path::Path};
+
+pub struct FontMetrics {
+    font: FontVec,
+}
+
+impl fmt::Debug for FontMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FontMetrics")
+            .field("glyph_count", &self.font.glyph_count())
+            .finish()
+    }
+}
+
+impl FontMetrics {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let data =
+            std::fs::read(path).with_context(|| format!("读取字体文件 {} 失败", path.display()))?;
+        Self::from_vec(data).with_context(|| format!("解析字体文件 {} 失败", path.display()))
+    }
+
+    pub fn from_vec(data: Vec<u8>) -> Result<Self> {
+        let font = FontVec::try_from_vec(data)?;
+        Ok(Self { font })
+    }
+
+    /// 计算文本在给定字号下的像素宽度，包括字距调整（kerning）
+    ///
+    /// ASS 的字号对应的是字体的行高（ascent - descent），和 [`PxScale`] 的定义相同。
+    /// 字体中没有的字符（字形 0，即 `.notdef`）使用 `fallback` 计算宽度
+    pub fn text_width(&self, text: &str, font_size: f64, fallback: impl Fn(char) -> f64) -> f64 {
+        let font = self.font.as_scaled(PxScale::from(font_size as f32));
+        let mut width = 0.0;
+        let mut last = None;
+        for ch in text.chars() {
+            let id = font.glyph_id(ch);
+            if id.0 == 0 {
+                width += fallback(ch);
+                last = None;
+                continue;
+            }
+            if let Some(last) = last {
+                width += font.kern(last, id) as f64;
+            }
+            width += font.h_advance(id) as f64;
+            last = Some(id);
+        }
+        width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_glyph() {
+        // 只有 A 一个字形，A 的宽度为 540，行高为 1024 + 400
+        let metrics =
+            FontMetrics::from_vec(include_bytes!("../resources/demo.ttf").to_vec()).unwrap();
+        let a = metrics.text_width("A", 142.4, |_| unreachable!());
+        assert!((a - 54.0).abs() < 0.01, "{a}");
+        // 其他字符不使用 .notdef 的宽度
+        let width = metrics.text_width("A中b", 142.4, |ch| if ch.is_ascii() { 1.0 } else { 10.0 });
+        assert!((width - 65.0).abs() < 0.01, "{width}");
+    }
+}
@@ -0,0 +1,142 @@
+//! 合并连击礼物：同一个用户在一段时间内送出的同一种礼物合并为一条
+use crate::{danmu::DanmuType, Danmu};
+use anyhow::Result;
+use std::collections::VecDeque;
+
+pub struct GiftCombo<I> {
+    inner: I,
+    /// 从连击的第一个礼物开始计算的时间窗口
+    window: f64,
+    /// 还没有输出的弹幕和连击，按开始时间排序。
+    /// 连击在时间窗口结束前不能输出，它后面的弹幕也要等它输出之后才能输出，保证输出按时间排序
+    pending: VecDeque<Danmu>,
+    /// 最近读到的弹幕的时间
+    now: f64,
+    /// 输入已经结束
+    done: bool,
+}
+
+impl<I> GiftCombo<I>
+where
+    I: Iterator<Item = Result<Danmu>>,
+{
+    pub fn new(inner: I, window: f64) -> Self {
+        Self {
+            inner,
+            window,
+            pending: VecDeque::new(),
+            now: f64::MIN,
+            done: false,
+        }
+    }
+}
+
+impl<I> Iterator for GiftCombo<I>
+where
+    I: Iterator<Item = Result<Danmu>>,
+{
+    type Item = Result<Danmu>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.pending.front() {
+                // 普通弹幕前面的连击都已经输出，可以直接输出；连击要等时间窗口结束
+                Some(front)
+                    if self.done
+                        || !matches!(front.r#type, DanmuType::Gift { .. })
+                        || front.timeline_s + self.window < self.now =>
+                {
+                    return self.pending.pop_front().map(Ok);
+                }
+                None if self.done => return None,
+                _ => {}
+            }
+            let danmu = match self.inner.next() {
+                Some(Ok(danmu)) => danmu,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.done = true;
+                    continue;
+                }
+            };
+            self.now = danmu.timeline_s;
+
+            let DanmuType::Gift {
+                user,
+                gift_name,
+                count,
+            } = &danmu.r#type
+            else {
+                self.pending.push_back(danmu);
+                continue;
+            };
+            // 时间窗口已经结束的连击不再合并
+            let window = self.window;
+            let now = self.now;
+            let combo = self.pending.iter_mut().find(|d| {
+                d.timeline_s + window >= now
+                    && matches!(&d.r#type, DanmuType::Gift { user: u, gift_name: g, .. } if u == user && g == gift_name)
+            });
+            match combo {
+                Some(Danmu {
+                    r#type: DanmuType::Gift { count: total, .. },
+                    ..
+                }) => {
+                    *total += count;
+                }
+                _ => self.pending.push_back(danmu),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gift(timeline_s: f64, user: &str, count: u32) -> Result<Danmu> {
+        Ok(Danmu {
+            timeline_s,
+            r#type: DanmuType::Gift {
+                user: user.to_string(),
+                gift_name: "小心心".to_string(),
+                count,
+            },
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn merge_combo() {
+        let input = vec![
+            gift(0.0, "A", 1),
+            gift(1.0, "B", 1),
+            gift(2.0, "A", 10),
+            Ok(Danmu {
+                timeline_s: 3.0,
+                ..Default::default()
+            }),
+            gift(4.0, "A", 1),
+            // 超出 A 的时间窗口，重新开始连击
+            gift(6.0, "A", 1),
+        ];
+        let output = GiftCombo::new(input.into_iter(), 5.0)
+            .map(|d| {
+                let d = d.unwrap();
+                match d.r#type {
+                    DanmuType::Gift { user, count, .. } => (d.timeline_s, user, count),
+                    _ => (d.timeline_s, String::new(), 0),
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            output,
+            vec![
+                (0.0, "A".to_string(), 12),
+                (1.0, "B".to_string(), 1),
+                (3.0, String::new(), 0),
+                (6.0, "A".to_string(), 1),
+            ]
+        );
+    }
+}
@@ -1,14 +1,15 @@
 use anyhow::{Context, Result};
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum InputType {
     File(PathBuf),
     Folder(PathBuf),
-    /// 如 `https://www.bilibili.com/video/BV1z44y1E7m6`
+    /// 如 `https://www.bilibili.com/video/BV1z44y1E7m6`，p 可以是 `p=2` 或者 `p=3-7`
     BV {
         bv: String,
-        p: Option<u32>,
+        p: Option<RangeInclusive<u32>>,
     },
     /// 如 `https://www.bilibili.com/bangumi/play/ss28296`
     Season {
@@ -18,24 +19,36 @@ pub enum InputType {
     Episode {
         episode_id: u64,
     },
+    /// 如 `https://b23.tv/abcdEFG`，需要用 [`InputType::resolve`] 请求跳转地址
+    ShortLink(url::Url),
 }
 
+/// 可以省略 `https://` 的域名
+const URL_PREFIXES: &[&str] = &[
+    "b23.tv/",
+    "bilibili.com/",
+    "www.bilibili.com/",
+    "m.bilibili.com/",
+];
+
 impl std::str::FromStr for InputType {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.starts_with("http") {
-            if let Ok(url) = url::Url::parse(s) {
-                info!("输入类型为 URL，解析中...");
-                return Self::from_url(url);
-            }
+        let url = if s.starts_with("http") {
+            url::Url::parse(s).ok()
+        } else if URL_PREFIXES.iter().any(|prefix| s.starts_with(prefix)) {
+            url::Url::parse(&format!("https://{s}")).ok()
+        } else {
+            None
+        };
+        if let Some(url) = url {
+            info!("输入类型为 URL，解析中...");
+            return Self::from_url(url);
         }
         if s.chars().all(|c| c.is_ascii_alphanumeric()) {
-            if s.starts_with("BV") {
-                return Ok(InputType::BV {
-                    bv: s.to_string(),
-                    p: None,
-                });
+            if let Ok(t) = Self::from_video_id(s, None) {
+                return Ok(t);
             }
             if let Ok(t) = Self::from_episode_or_season_str(s) {
                 return Ok(t);
@@ -53,24 +66,30 @@ impl std::str::FromStr for InputType {
 
 impl InputType {
     pub fn from_url(url: url::Url) -> Result<Self> {
-        if url.domain() != Some("www.bilibili.com") {
-            anyhow::bail!("不支持的域名 {}", url.domain().unwrap_or(""));
+        match url.domain() {
+            Some("b23.tv") => return Ok(InputType::ShortLink(url)),
+            Some("www.bilibili.com" | "bilibili.com" | "m.bilibili.com") => {}
+            domain => anyhow::bail!("不支持的域名 {}", domain.unwrap_or("")),
+        }
+        let query = |key: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.into_owned())
+        };
+        let p = query("p").and_then(|v| parse_range(&v));
+        // festival、list 等页面的视频放在 bvid 参数中
+        if let Some(bv) = query("bvid") {
+            return Self::from_video_id(&bv, p);
         }
+
         let mut path = url
             .path_segments()
             .context("解析 URL 的 path segments 错误")?;
         let first_segment = path.next().context("解析 URL 的 path segments 错误")?;
         match first_segment {
             "video" => {
-                let bv = path
-                    .next()
-                    .context("解析 URL 的 path segments 错误")?
-                    .to_string();
-                let p = url
-                    .query_pairs()
-                    .find(|(k, _)| k == "p")
-                    .and_then(|(_, v)| v.parse().ok());
-                Ok(InputType::BV { bv, p })
+                let id = path.next().context("解析 URL 的 path segments 错误")?;
+                Self::from_video_id(id, p)
             }
             "bangumi" => {
                 anyhow::ensure!(
@@ -86,6 +105,40 @@ impl InputType {
         }
     }
 
+    /// BV 号或者 av 号，av 号会转换为 BV 号
+    fn from_video_id(id: &str, p: Option<RangeInclusive<u32>>) -> Result<Self> {
+        if id.starts_with("BV") && id.len() == 12 {
+            return Ok(InputType::BV {
+                bv: id.to_string(),
+                p,
+            });
+        }
+        let aid = id
+            .strip_prefix("av")
+            .or_else(|| id.strip_prefix("AV"))
+            .and_then(|aid| aid.parse().ok())
+            .with_context(|| format!("不合法的视频 id {id}，应该是 BV 号或者 av 号"))?;
+        Ok(InputType::BV {
+            bv: av_to_bv(aid)?,
+            p,
+        })
+    }
+
+    /// 请求短链接的跳转地址，其他类型原样返回
+    pub async fn resolve(self, client: &crate::bilibili::Client) -> Result<Self> {
+        let InputType::ShortLink(url) = self else {
+            return Ok(self);
+        };
+        let target = crate::bilibili::resolve_short_link(client, &url).await?;
+        info!("短链接 {} 跳转到 {}", url, target);
+        match Self::from_url(target)? {
+            InputType::ShortLink(target) => {
+                anyhow::bail!("短链接 {} 跳转到了另一个短链接 {}", url, target)
+            }
+            t => Ok(t),
+        }
+    }
+
     pub fn from_episode_or_season_str(s: &str) -> Result<Self> {
         match s.chars().take(2).collect::<String>().as_str() {
             "ss" => {
@@ -113,6 +166,35 @@ impl InputType {
     }
 }
 
+/// av 号转 BV 号，见 https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/misc/bvid_desc.md
+fn av_to_bv(aid: u64) -> Result<String> {
+    const XOR_CODE: u64 = 23442827791579;
+    const MAX_AID: u64 = 1 << 51;
+    const ALPHABET: &[u8] = b"FcwAPNKTMug3GV5Lj7EJnHpWsx4tb8haYeviqBz6rkCy12mUSDQX9RdoZf";
+    const ENCODE_MAP: [usize; 9] = [8, 7, 0, 5, 1, 3, 2, 4, 6];
+
+    anyhow::ensure!(aid > 0 && aid < MAX_AID, "av 号 {} 超出范围", aid);
+    let mut bv = [0u8; 9];
+    let mut tmp = (MAX_AID | aid) ^ XOR_CODE;
+    for i in ENCODE_MAP {
+        bv[i] = ALPHABET[(tmp % 58) as usize];
+        tmp /= 58;
+    }
+    Ok(format!("BV1{}", std::str::from_utf8(&bv)?))
+}
+
+/// 解析分 p 或剧集的范围，`3` 或者 `3-7`，从 1 开始，两端都包含
+pub fn parse_range(s: &str) -> Option<RangeInclusive<u32>> {
+    let (start, end) = match s.split_once('-') {
+        Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+        None => {
+            let p = s.trim().parse().ok()?;
+            (p, p)
+        }
+    };
+    (start >= 1 && start <= end).then_some(start..=end)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -136,7 +218,17 @@ mod tests {
                 .unwrap(),
             T::BV {
                 bv: "BV1z44y1E7m6".to_string(),
-                p: Some(2)
+                p: Some(2..=2)
+            }
+        );
+
+        assert_eq!(
+            "https://www.bilibili.com/video/BV1z44y1E7m6?p=3-7"
+                .parse::<T>()
+                .unwrap(),
+            T::BV {
+                bv: "BV1z44y1E7m6".to_string(),
+                p: Some(3..=7)
             }
         );
 
@@ -149,6 +241,74 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_other_video_urls() {
+        let bv = |bv: &str, p: Option<RangeInclusive<u32>>| T::BV {
+            bv: bv.to_string(),
+            p,
+        };
+        for (input, expected) in [
+            ("av170001", bv("BV17x411w7KC", None)),
+            ("AV170001", bv("BV17x411w7KC", None)),
+            (
+                "https://www.bilibili.com/video/av170001?p=2",
+                bv("BV17x411w7KC", Some(2..=2)),
+            ),
+            (
+                "https://m.bilibili.com/video/BV1z44y1E7m6?p=2&share_source=copy_web",
+                bv("BV1z44y1E7m6", Some(2..=2)),
+            ),
+            (
+                "https://bilibili.com/video/BV1z44y1E7m6/",
+                bv("BV1z44y1E7m6", None),
+            ),
+            (
+                "www.bilibili.com/video/BV1z44y1E7m6",
+                bv("BV1z44y1E7m6", None),
+            ),
+            (
+                "https://www.bilibili.com/festival/2022bnj?bvid=BV1ge411d7Tt&spm_id_from=333.1007",
+                bv("BV1ge411d7Tt", None),
+            ),
+            (
+                "https://www.bilibili.com/list/watchlater?oid=170001&bvid=BV17x411w7KC&p=3",
+                bv("BV17x411w7KC", Some(3..=3)),
+            ),
+            (
+                "https://www.bilibili.com/list/ml1234567?bvid=BV1z44y1E7m6",
+                bv("BV1z44y1E7m6", None),
+            ),
+            (
+                "https://m.bilibili.com/bangumi/play/ep473502",
+                T::Episode { episode_id: 473502 },
+            ),
+        ] {
+            assert_eq!(input.parse::<T>().unwrap(), expected, "{input}");
+        }
+        assert_eq!(av_to_bv(1054803170).unwrap(), "BV1mH4y1u7UA");
+        assert!("https://www.bilibili.com/video/xyz".parse::<T>().is_err());
+        assert!("https://www.youtube.com/watch?v=1".parse::<T>().is_err());
+    }
+
+    #[test]
+    fn parse_short_link() {
+        for input in ["https://b23.tv/abcdEFG", "b23.tv/abcdEFG"] {
+            assert_eq!(
+                input.parse::<T>().unwrap(),
+                T::ShortLink(url::Url::parse("https://b23.tv/abcdEFG").unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn range() {
+        assert_eq!(parse_range("1"), Some(1..=1));
+        assert_eq!(parse_range("3-7"), Some(3..=7));
+        assert_eq!(parse_range("0"), None);
+        assert_eq!(parse_range("7-3"), None);
+        assert_eq!(parse_range("a-b"), None);
+    }
+
     #[test]
     fn parse_season_or_episode() {
         assert_eq!(
@@ -7,15 +7,35 @@ mod ass_writer;
 pub mod bilibili;
 mod canvas;
 mod cli;
+mod config_file;
 mod danmu;
+mod dedup;
 mod drawable;
+mod emote;
+mod file_format;
+mod filter;
+mod font_metrics;
+mod gift_combo;
 mod input_type;
+pub mod live;
+mod reorder;
+mod srt_writer;
+mod watch;
+mod webvtt_writer;
+mod writer;
 mod xml_parser;
 
 pub use ass_writer::AssWriter;
-pub use canvas::{Canvas, Config as CanvasConfig};
-pub use cli::{convert, Args};
-pub use danmu::Danmu;
+pub use canvas::{Canvas, Config as CanvasConfig, ReverseMode};
+pub use cli::{convert, convert_streaming, Args};
+pub use danmu::{Danmu, Pool};
 pub use drawable::{DrawEffect, Drawable};
+pub use emote::Emotes;
+pub use file_format::FileFormat;
+pub use filter::Filter;
+pub use font_metrics::FontMetrics;
 pub use input_type::InputType;
+pub use srt_writer::SrtWriter;
+pub use webvtt_writer::WebVttWriter;
+pub use writer::{OutputFormat, Writer};
 pub use xml_parser::Parser;
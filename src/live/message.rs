@@ -0,0 +1,206 @@
+//! 直播间推送的 JSON 消息，只解析需要记录的弹幕、醒目留言、礼物和上舰
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::io::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiveEvent {
+    Danmu {
+        mode: u32,
+        fontsize: u32,
+        color: u32,
+        /// 发送时间的毫秒级时间戳
+        timestamp_ms: u64,
+        uid: u64,
+        user: String,
+        text: String,
+    },
+    SuperChat {
+        uid: u64,
+        user: String,
+        /// 价格（元）
+        price: f64,
+        /// 在直播间中的显示时长（秒）
+        time: u64,
+        message: String,
+    },
+    Gift {
+        uid: u64,
+        user: String,
+        gift_name: String,
+        count: u32,
+    },
+    Guard {
+        uid: u64,
+        user: String,
+        level: u32,
+        count: u32,
+    },
+}
+
+/// 数字有时会以字符串的形式出现
+fn as_u64(v: &Value) -> Option<u64> {
+    v.as_u64().or_else(|| v.as_str()?.parse().ok())
+}
+
+fn as_str(v: &Value) -> String {
+    v.as_str().unwrap_or_default().to_string()
+}
+
+impl LiveEvent {
+    /// 解析一条消息，不需要记录的消息返回 `None`
+    pub fn from_json(body: &[u8]) -> Result<Option<Self>> {
+        let msg: Value = serde_json::from_slice(body).context("消息不是合法的 JSON")?;
+        let cmd = msg["cmd"].as_str().context("消息中没有 cmd")?;
+        // DANMU_MSG 可能带有后缀，如 DANMU_MSG:4:0:2:2:2:0
+        let cmd = cmd.split(':').next().unwrap_or(cmd);
+        let event = match cmd {
+            "DANMU_MSG" => {
+                let info = &msg["info"];
+                let get = |i: usize| as_u64(&info[0][i]).context("弹幕消息格式错误");
+                LiveEvent::Danmu {
+                    mode: get(1)? as u32,
+                    fontsize: get(2)? as u32,
+                    color: get(3)? as u32,
+                    timestamp_ms: get(4)?,
+                    uid: as_u64(&info[2][0]).unwrap_or_default(),
+                    user: as_str(&info[2][1]),
+                    text: info[1].as_str().context("弹幕消息中没有内容")?.to_string(),
+                }
+            }
+            "SUPER_CHAT_MESSAGE" => {
+                let data = &msg["data"];
+                LiveEvent::SuperChat {
+                    uid: as_u64(&data["uid"]).unwrap_or_default(),
+                    user: as_str(&data["user_info"]["uname"]),
+                    price: data["price"].as_f64().context("醒目留言中没有价格")?,
+                    time: as_u64(&data["time"]).unwrap_or_default(),
+                    message: as_str(&data["message"]),
+                }
+            }
+            "SEND_GIFT" => {
+                let data = &msg["data"];
+                LiveEvent::Gift {
+                    uid: as_u64(&data["uid"]).unwrap_or_default(),
+                    user: as_str(&data["uname"]),
+                    gift_name: as_str(&data["giftName"]),
+                    count: as_u64(&data["num"]).context("礼物中没有数量")? as u32,
+                }
+            }
+            "GUARD_BUY" => {
+                let data = &msg["data"];
+                LiveEvent::Guard {
+                    uid: as_u64(&data["uid"]).unwrap_or_default(),
+                    user: as_str(&data["username"]),
+                    level: as_u64(&data["guard_level"]).context("上舰中没有等级")? as u32,
+                    count: as_u64(&data["num"]).unwrap_or(1) as u32,
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(event))
+    }
+
+    /// 按照录播姬的格式写入一行 XML，`ts` 为距离录制开始的秒数
+    pub fn write_xml<W: Write>(&self, mut w: W, ts: f64) -> Result<()> {
+        use crate::bilibili::escape;
+        match self {
+            LiveEvent::Danmu {
+                mode,
+                fontsize,
+                color,
+                timestamp_ms,
+                uid,
+                user,
+                text,
+            } => writeln!(
+                w,
+                "<d p=\"{ts:.3},{mode},{fontsize},{color},{timestamp_ms},0,{uid},0\" user=\"{}\">{}</d>",
+                escape(user),
+                escape(text)
+            )?,
+            LiveEvent::SuperChat {
+                uid,
+                user,
+                price,
+                time,
+                message,
+            } => writeln!(
+                w,
+                "<sc ts=\"{ts:.3}\" user=\"{}\" uid=\"{uid}\" price=\"{price}\" time=\"{time}\">{}</sc>",
+                escape(user),
+                escape(message)
+            )?,
+            LiveEvent::Gift {
+                uid,
+                user,
+                gift_name,
+                count,
+            } => writeln!(
+                w,
+                "<gift ts=\"{ts:.3}\" user=\"{}\" uid=\"{uid}\" giftname=\"{}\" giftcount=\"{count}\" />",
+                escape(user),
+                escape(gift_name)
+            )?,
+            LiveEvent::Guard {
+                uid,
+                user,
+                level,
+                count,
+            } => writeln!(
+                w,
+                "<guard ts=\"{ts:.3}\" user=\"{}\" uid=\"{uid}\" level=\"{level}\" count=\"{count}\" />",
+                escape(user)
+            )?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_messages() {
+        let danmu = LiveEvent::from_json(
+            r##"{"cmd":"DANMU_MSG:4:0:2:2:2:0","info":[[0,1,25,14893055,1647777083220,1647776219,0,"1537d7c7",0,0,5,"#1453BAFF,#4C2263A2,#3353BAFF",0,"{}","{}"],"快快快",[398452452,"小马368100",0,0,0,10000,1,"#00D1F1"],[22,"嘉心糖","嘉然今天吃什么",22637261],[5,0,9868950,">50000",0],["",""],0,3,null,{"ts":1647777083,"ct":"7581F4E3"},0,0,null,null,0,105]}"##.as_bytes(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            danmu,
+            LiveEvent::Danmu {
+                mode: 1,
+                fontsize: 25,
+                color: 14893055,
+                timestamp_ms: 1647777083220,
+                uid: 398452452,
+                user: "小马368100".to_string(),
+                text: "快快快".to_string(),
+            }
+        );
+
+        let sc = LiveEvent::from_json(
+            r#"{"cmd":"SUPER_CHAT_MESSAGE","data":{"id":1,"uid":"123","price":30,"time":60,"message":"主播好","user_info":{"uname":"A&B"}}}"#.as_bytes(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            sc,
+            LiveEvent::SuperChat {
+                uid: 123,
+                user: "A&B".to_string(),
+                price: 30.0,
+                time: 60,
+                message: "主播好".to_string(),
+            }
+        );
+
+        assert_eq!(
+            LiveEvent::from_json(r#"{"cmd":"INTERACT_WORD","data":{}}"#.as_bytes()).unwrap(),
+            None
+        );
+        assert!(LiveEvent::from_json(r#"{"cmd":"DANMU_MSG","info":[]}"#.as_bytes()).is_err());
+    }
+}
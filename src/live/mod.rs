@@ -0,0 +1,11 @@
+//! 录制直播间的弹幕
+mod packet;
+pub use packet::Packet;
+
+mod message;
+pub use message::LiveEvent;
+
+mod room;
+
+mod recorder;
+pub use recorder::{record, XmlWriter};
@@ -0,0 +1,126 @@
+//! 直播弹幕服务器的数据包
+//!
+//! 每个包由 16 字节的大端 header 和 body 组成：
+//! 包长度（u32）、header 长度（u16）、协议版本（u16）、操作码（u32）、序号（u32）。
+//! 协议版本 2 和 3 的 body 分别是 zlib 和 brotli 压缩过的多个包
+use anyhow::{Context, Result};
+use std::io::Read;
+
+pub const HEADER_LEN: usize = 16;
+
+/// 操作码
+pub mod op {
+    pub const HEARTBEAT: u32 = 2;
+    /// body 为 u32 的人气值
+    pub const HEARTBEAT_REPLY: u32 = 3;
+    /// body 为 JSON 格式的消息
+    pub const MESSAGE: u32 = 5;
+    pub const AUTH: u32 = 7;
+    pub const AUTH_REPLY: u32 = 8;
+}
+
+/// 协议版本
+pub mod protover {
+    /// 未压缩
+    pub const INT: u16 = 1;
+    pub const ZLIB: u16 = 2;
+    pub const BROTLI: u16 = 3;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub protover: u16,
+    pub op: u32,
+    pub body: Vec<u8>,
+}
+
+impl Packet {
+    pub fn new(protover: u16, op: u32, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            protover,
+            op,
+            body: body.into(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN + self.body.len());
+        buf.extend(((HEADER_LEN + self.body.len()) as u32).to_be_bytes());
+        buf.extend((HEADER_LEN as u16).to_be_bytes());
+        buf.extend(self.protover.to_be_bytes());
+        buf.extend(self.op.to_be_bytes());
+        buf.extend(1u32.to_be_bytes());
+        buf.extend(&self.body);
+        buf
+    }
+
+    /// 解析一个 WebSocket 消息，一个消息中可能有多个包，压缩过的包会被解压展开
+    pub fn decode_all(mut data: &[u8]) -> Result<Vec<Self>> {
+        let mut packets = vec![];
+        while !data.is_empty() {
+            anyhow::ensure!(data.len() >= HEADER_LEN, "数据包 header 不完整");
+            let packet_len = u32::from_be_bytes(data[0..4].try_into()?) as usize;
+            let header_len = u16::from_be_bytes(data[4..6].try_into()?) as usize;
+            let protover = u16::from_be_bytes(data[6..8].try_into()?);
+            let op = u32::from_be_bytes(data[8..12].try_into()?);
+            anyhow::ensure!(
+                header_len >= HEADER_LEN && header_len <= packet_len && packet_len <= data.len(),
+                "数据包长度错误：包长度 {}，header 长度 {}，剩余 {} 字节",
+                packet_len,
+                header_len,
+                data.len()
+            );
+            let body = &data[header_len..packet_len];
+            data = &data[packet_len..];
+
+            match protover {
+                protover::ZLIB => {
+                    let mut buf = vec![];
+                    flate2::read::ZlibDecoder::new(body)
+                        .read_to_end(&mut buf)
+                        .context("zlib 解压失败")?;
+                    packets.extend(Self::decode_all(&buf)?);
+                }
+                protover::BROTLI => {
+                    let mut buf = vec![];
+                    brotli_decompressor::Decompressor::new(body, 4096)
+                        .read_to_end(&mut buf)
+                        .context("brotli 解压失败")?;
+                    packets.extend(Self::decode_all(&buf)?);
+                }
+                _ => packets.push(Self::new(protover, op, body)),
+            }
+        }
+        Ok(packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn decode_compressed() {
+        let a = Packet::new(protover::INT, op::MESSAGE, r#"{"cmd":"A"}"#);
+        let b = Packet::new(protover::INT, op::MESSAGE, r#"{"cmd":"B"}"#);
+        let inner = [a.encode(), b.encode()].concat();
+
+        let mut zlib = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        zlib.write_all(&inner).unwrap();
+        let zlib = Packet::new(protover::ZLIB, op::MESSAGE, zlib.finish().unwrap());
+
+        let mut brotli = brotli::CompressorWriter::new(vec![], 4096, 5, 22);
+        brotli.write_all(&inner).unwrap();
+        let brotli = Packet::new(protover::BROTLI, op::MESSAGE, brotli.into_inner());
+
+        let heartbeat = Packet::new(protover::INT, op::HEARTBEAT_REPLY, 1234u32.to_be_bytes());
+        let data = [heartbeat.encode(), zlib.encode(), brotli.encode()].concat();
+        assert_eq!(
+            Packet::decode_all(&data).unwrap(),
+            vec![heartbeat, a.clone(), b.clone(), a, b]
+        );
+
+        assert!(Packet::decode_all(&data[..25]).is_err());
+    }
+}
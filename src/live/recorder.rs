@@ -0,0 +1,318 @@
+//! 连接直播间的弹幕服务器，将弹幕保存为录播姬格式的 XML
+use super::packet::{op, protover, Packet};
+use super::{room, LiveEvent};
+use crate::bilibili::{escape, Client};
+use anyhow::{Context, Result};
+use futures::{SinkExt, StreamExt};
+use std::future::Future;
+use std::io::Write;
+use std::time::{Duration, Instant, SystemTime};
+use tokio_tungstenite::tungstenite::Message;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// 录播姬格式的 XML，可以直接用 [`crate::Parser`] 读取
+pub struct XmlWriter<W: Write> {
+    w: W,
+    start: Instant,
+    count: usize,
+}
+
+impl<W: Write> XmlWriter<W> {
+    pub fn new(mut w: W, room_id: u64) -> Result<Self> {
+        let start_time = chrono::DateTime::<chrono::Utc>::from(SystemTime::now()).to_rfc3339();
+        write!(
+            w,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+            <i>\n\
+            <chatserver>chat.bilibili.com</chatserver>\n\
+            <chatid>0</chatid>\n\
+            <mission>0</mission>\n\
+            <maxlimit>1000</maxlimit>\n\
+            <state>0</state>\n\
+            <real_name>0</real_name>\n\
+            <source>0</source>\n\
+            <BililiveRecorder version=\"danmu2ass {}\" />\n\
+            <BililiveRecorderRecordInfo roomid=\"{}\" start_time=\"{}\" />\n",
+            env!("CARGO_PKG_VERSION"),
+            room_id,
+            escape(&start_time)
+        )?;
+        Ok(Self {
+            w,
+            start: Instant::now(),
+            count: 0,
+        })
+    }
+
+    /// 写入一条消息，时间为距离录制开始的时间
+    pub fn write(&mut self, event: &LiveEvent) -> Result<()> {
+        event.write_xml(&mut self.w, self.start.elapsed().as_secs_f64())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.w.flush()?;
+        Ok(())
+    }
+
+    /// 闭合 XML，`--watch` 模式以此判断文件已经写完
+    pub fn finish(mut self) -> Result<W> {
+        writeln!(self.w, "</i>")?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+/// 录制直播间的弹幕直到 `shutdown` 完成，断线后自动重连
+///
+/// `ws_url` 不为空时连接这个地址而不是接口返回的弹幕服务器
+pub async fn record<W, S>(
+    client: &Client,
+    room_id: u64,
+    ws_url: Option<&str>,
+    writer: &mut XmlWriter<W>,
+    shutdown: S,
+) -> Result<()>
+where
+    W: Write,
+    S: Future<Output = ()>,
+{
+    tokio::pin!(shutdown);
+    let real_room_id = room::get_real_room_id(client, room_id).await?;
+    if real_room_id != room_id {
+        info!("直播间 {} 的真实房间号为 {}", room_id, real_room_id);
+    }
+
+    let mut delay = RECONNECT_DELAY;
+    loop {
+        let result = tokio::select! {
+            _ = &mut shutdown => break,
+            result = connect(client, real_room_id, ws_url, writer, &mut delay) => result,
+        };
+        writer.flush()?;
+        match result {
+            Ok(()) => warn!(
+                "直播间 {} 的弹幕服务器断开了连接，{:?} 后重连",
+                room_id, delay
+            ),
+            Err(e) => warn!(
+                "直播间 {} 的弹幕连接错误：{:?}，{:?} 后重连",
+                room_id, e, delay
+            ),
+        }
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = tokio::time::sleep(delay) => {}
+        }
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+    info!("停止录制，共记录 {} 条弹幕", writer.count());
+    Ok(())
+}
+
+/// 连接一次弹幕服务器，直到连接断开。认证成功后重置重连的等待时间
+async fn connect<W: Write>(
+    client: &Client,
+    room_id: u64,
+    ws_url: Option<&str>,
+    writer: &mut XmlWriter<W>,
+    delay: &mut Duration,
+) -> Result<()> {
+    let server = room::get_server(client, room_id).await;
+    let url = ws_url.unwrap_or(&server.url);
+    debug!("连接弹幕服务器 {}", url);
+    let (mut ws, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .with_context(|| format!("连接弹幕服务器 {url} 失败"))?;
+
+    let auth = serde_json::json!({
+        "uid": 0,
+        "roomid": room_id,
+        "protover": protover::BROTLI,
+        "platform": "web",
+        "type": 2,
+        "key": server.token,
+    });
+    ws.send(Message::Binary(
+        Packet::new(protover::INT, op::AUTH, auth.to_string()).encode(),
+    ))
+    .await?;
+
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => {
+                let packet = Packet::new(protover::INT, op::HEARTBEAT, "[object Object]");
+                ws.send(Message::Binary(packet.encode())).await?;
+                writer.flush()?;
+            }
+            msg = ws.next() => {
+                let data = match msg {
+                    None | Some(Ok(Message::Close(_))) => return Ok(()),
+                    Some(Ok(Message::Binary(data))) => data,
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e.into()),
+                };
+                for packet in Packet::decode_all(&data)? {
+                    handle_packet(packet, writer, delay)?;
+                }
+            }
+        }
+    }
+}
+
+fn handle_packet<W: Write>(
+    packet: Packet,
+    writer: &mut XmlWriter<W>,
+    delay: &mut Duration,
+) -> Result<()> {
+    match packet.op {
+        op::AUTH_REPLY => {
+            let reply: serde_json::Value =
+                serde_json::from_slice(&packet.body).context("认证回复无法解析")?;
+            anyhow::ensure!(reply["code"] == 0, "弹幕服务器认证失败：{}", reply);
+            info!("已连接弹幕服务器");
+            *delay = RECONNECT_DELAY;
+        }
+        op::HEARTBEAT_REPLY => {
+            if let Ok(popularity) = packet.body.as_slice().try_into() {
+                debug!("人气值 {}", u32::from_be_bytes(popularity));
+            }
+        }
+        op::MESSAGE => match LiveEvent::from_json(&packet.body) {
+            Ok(Some(event)) => writer.write(&event)?,
+            Ok(None) => {}
+            Err(e) => debug!(
+                "消息 {} 解析失败：{:?}",
+                String::from_utf8_lossy(&packet.body),
+                e
+            ),
+        },
+        _ => debug!("未知的操作码 {}", packet.op),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bilibili::client::tests::{client, mock_server};
+    use crate::danmu::DanmuType;
+    use std::io::Write;
+
+    /// 录制时收到的消息，去掉了无关的字段
+    const FRAMES: [&str; 5] = [
+        r##"{"cmd":"DANMU_MSG","info":[[0,1,25,14893055,1647777083220,1647776219,0,"1537d7c7",0,0,5,"",0,"{}","{}"],"快快快",[398452452,"小马368100",0,0,0,10000,1,"#00D1F1"],[],[5,0,9868950,">50000",0],["",""],0,3,null,{"ts":1647777083,"ct":"7581F4E3"},0,0,null,null,0,105]}"##,
+        r#"{"cmd":"SEND_GIFT","data":{"action":"投喂","giftId":30607,"giftName":"小心心","num":1,"uid":197750709,"uname":"粉色羽毛球_Official","coin_type":"silver"}}"#,
+        r#"{"cmd":"INTERACT_WORD","data":{"uid":1,"uname":"路人"}}"#,
+        r#"{"cmd":"SUPER_CHAT_MESSAGE","data":{"id":1,"uid":123,"price":30,"time":60,"message":"<主播好>","user_info":{"uname":"A&B"}}}"#,
+        r#"{"cmd":"GUARD_BUY","data":{"uid":456,"username":"C","guard_level":3,"num":1,"price":198000}}"#,
+    ];
+
+    /// 模拟弹幕服务器：第一个连接中回复认证并发送消息后断开，第二个连接到来时结束录制
+    async fn ws_server(shutdown: tokio::sync::oneshot::Sender<()>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let Some(Ok(Message::Binary(data))) = ws.next().await else {
+                panic!("第一个包应该是认证包");
+            };
+            let auth = Packet::decode_all(&data).unwrap().remove(0);
+            assert_eq!(auth.op, op::AUTH);
+            let auth: serde_json::Value = serde_json::from_slice(&auth.body).unwrap();
+            assert_eq!(auth["roomid"], 22637261);
+            assert_eq!(auth["key"], "token");
+
+            let reply = Packet::new(protover::INT, op::AUTH_REPLY, r#"{"code":0}"#);
+            ws.send(Message::Binary(reply.encode())).await.unwrap();
+
+            let packets = |frames: &[&str]| -> Vec<u8> {
+                frames
+                    .iter()
+                    .flat_map(|f| Packet::new(protover::INT, op::MESSAGE, *f).encode())
+                    .collect()
+            };
+            let mut brotli = brotli::CompressorWriter::new(vec![], 4096, 5, 22);
+            brotli.write_all(&packets(&FRAMES[..3])).unwrap();
+            let brotli = Packet::new(protover::BROTLI, op::MESSAGE, brotli.into_inner());
+            let mut zlib = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+            zlib.write_all(&packets(&FRAMES[3..])).unwrap();
+            let zlib = Packet::new(protover::ZLIB, op::MESSAGE, zlib.finish().unwrap());
+            ws.send(Message::Binary(brotli.encode())).await.unwrap();
+            ws.send(Message::Binary(zlib.encode())).await.unwrap();
+            ws.close(None).await.unwrap();
+
+            let _ = listener.accept().await.unwrap();
+            shutdown.send(()).unwrap();
+        });
+        format!("ws://{addr}")
+    }
+
+    #[tokio::test]
+    async fn record_replayed_frames() {
+        let base_url = mock_server(|path, _| {
+            let body = if path.starts_with("/room/v1/Room/room_init") {
+                assert!(path.contains("id=123"), "{path}");
+                r#"{"code":0,"msg":"ok","message":"ok","data":{"room_id":22637261,"short_id":123}}"#
+            } else {
+                r#"{"code":0,"message":"0","data":{"token":"token","host_list":[{"host":"localhost","port":2243,"wss_port":443,"ws_port":2244}]}}"#
+            };
+            (200, body.as_bytes().to_vec())
+        });
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let ws_url = ws_server(tx).await;
+
+        let mut writer = XmlWriter::new(vec![], 123).unwrap();
+        record(
+            &client(base_url),
+            123,
+            Some(&ws_url),
+            &mut writer,
+            async move {
+                rx.await.unwrap();
+            },
+        )
+        .await
+        .unwrap();
+        let xml = writer.finish().unwrap();
+
+        let danmus = crate::Parser::new(xml.as_slice())
+            .parse_gifts(true)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let types: Vec<_> = danmus.iter().map(|d| d.r#type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                DanmuType::Float,
+                DanmuType::Gift {
+                    user: "粉色羽毛球_Official".to_string(),
+                    gift_name: "小心心".to_string(),
+                    count: 1
+                },
+                DanmuType::SuperChat {
+                    user: "A&B".to_string(),
+                    price: 30
+                },
+                DanmuType::Guard {
+                    user: "C".to_string(),
+                    level: 3,
+                    count: 1
+                },
+            ]
+        );
+        assert!(danmus[0].content.ends_with("快快快"));
+        assert_eq!(danmus[0].rgb, (0xE3, 0x3F, 0xFF));
+        assert!(danmus[2].content.ends_with("<主播好>"));
+    }
+}
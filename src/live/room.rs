@@ -0,0 +1,95 @@
+//! 直播间信息：真实房间号以及弹幕服务器的地址和 token
+use crate::bilibili::Client;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+const ROOM_INIT_PATH: &str = "/room/v1/Room/room_init";
+const DANMU_INFO_PATH: &str = "/xlive/web-room/v1/index/getDanmuInfo";
+/// 获取弹幕服务器失败时使用的默认地址
+const DEFAULT_WS_URL: &str = "wss://broadcastlv.chat.bilibili.com/sub";
+
+#[derive(Debug, Deserialize)]
+struct LiveResponse<T> {
+    code: i64,
+
+    #[serde(default)]
+    message: String,
+
+    data: Option<T>,
+}
+
+async fn request<T: serde::de::DeserializeOwned>(
+    client: &Client,
+    path: &str,
+    room_id: u64,
+) -> Result<T> {
+    let text = client
+        .get_live(path, &[("id", room_id)])
+        .await?
+        .text()
+        .await?;
+    let resp: LiveResponse<T> =
+        serde_json::from_str(&text).with_context(|| format!("{path} 的返回无法解析"))?;
+    if resp.code != 0 {
+        debug!("response text = {}", text);
+        anyhow::bail!("code = {}, message = {}", resp.code, resp.message);
+    }
+    resp.data.context("返回中没有 data")
+}
+
+#[derive(Debug, Deserialize)]
+struct RoomInit {
+    room_id: u64,
+}
+
+/// 短号转换为真实房间号
+pub async fn get_real_room_id(client: &Client, room_id: u64) -> Result<u64> {
+    let init: RoomInit = request(client, ROOM_INIT_PATH, room_id)
+        .await
+        .with_context(|| format!("获取直播间 {room_id} 的信息失败"))?;
+    Ok(init.room_id)
+}
+
+#[derive(Debug, Deserialize)]
+struct Host {
+    host: String,
+    wss_port: u16,
+}
+
+#[derive(Debug, Deserialize)]
+struct DanmuInfo {
+    token: String,
+    host_list: Vec<Host>,
+}
+
+/// 弹幕服务器
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Server {
+    pub url: String,
+    /// 认证包中的 key
+    pub token: String,
+}
+
+/// 获取弹幕服务器，失败时使用默认地址和空 token 匿名连接
+pub async fn get_server(client: &Client, room_id: u64) -> Server {
+    match request::<DanmuInfo>(client, DANMU_INFO_PATH, room_id).await {
+        Ok(info) => Server {
+            url: info
+                .host_list
+                .first()
+                .map(|h| format!("wss://{}:{}/sub", h.host, h.wss_port))
+                .unwrap_or_else(|| DEFAULT_WS_URL.to_string()),
+            token: info.token,
+        },
+        Err(e) => {
+            warn!(
+                "获取直播间 {} 的弹幕服务器失败，使用默认地址：{:?}",
+                room_id, e
+            );
+            Server {
+                url: DEFAULT_WS_URL.to_string(),
+                token: String::new(),
+            }
+        }
+    }
+}
@@ -17,8 +17,8 @@ async fn main() -> Result<()> {
 
     let args = load_args()?;
     #[cfg(feature = "web")]
-    if !args.no_web {
-        return web::run_server().await;
+    if !args.no_web && args.command.is_none() {
+        return web::run_server(args.client_config()).await;
     }
 
     let pause = args.pause;
@@ -36,7 +36,7 @@ async fn main() -> Result<()> {
 }
 
 fn load_args() -> Result<Args> {
-    let mut args = Args::parse();
+    let mut args = Args::parse_with_config_file()?;
     args.check()?;
     Ok(args)
 }
@@ -0,0 +1,123 @@
+//! 流式转换时对基本有序的弹幕重新排序，只在内存中保留一个时间窗口内的弹幕
+use crate::Danmu;
+use anyhow::Result;
+use float_ord::FloatOrd;
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+struct Item {
+    /// (时间, 读入顺序)，时间相同时保持原有顺序
+    key: (FloatOrd<f64>, usize),
+    danmu: Danmu,
+}
+impl PartialEq for Item {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+impl Eq for Item {}
+impl PartialOrd for Item {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Item {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// 读入一条弹幕之后，比它早 `window` 秒以上的弹幕都可以输出了。
+///
+/// 比已经输出的弹幕晚到超过 `window` 秒的弹幕会被直接输出，此时输出不再严格有序。
+pub struct Reorder<I> {
+    inner: I,
+    window: f64,
+    heap: BinaryHeap<Reverse<Item>>,
+    seq: usize,
+    /// 读入的最晚时间
+    latest: f64,
+    finished: bool,
+}
+
+impl<I> Reorder<I>
+where
+    I: Iterator<Item = Result<Danmu>>,
+{
+    pub fn new(inner: I, window: f64) -> Self {
+        Self {
+            inner,
+            window,
+            heap: BinaryHeap::new(),
+            seq: 0,
+            latest: f64::MIN,
+            finished: false,
+        }
+    }
+
+    fn pop_ready(&mut self) -> Option<Danmu> {
+        let Reverse(top) = self.heap.peek()?;
+        if self.finished || top.key.0 .0 + self.window <= self.latest {
+            self.heap.pop().map(|Reverse(item)| item.danmu)
+        } else {
+            None
+        }
+    }
+}
+
+impl<I> Iterator for Reorder<I>
+where
+    I: Iterator<Item = Result<Danmu>>,
+{
+    type Item = Result<Danmu>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(danmu) = self.pop_ready() {
+                return Some(Ok(danmu));
+            }
+            if self.finished {
+                return None;
+            }
+            match self.inner.next() {
+                Some(Ok(danmu)) => {
+                    if danmu.timeline_s + self.window < self.latest {
+                        debug!(
+                            "弹幕乱序超过 {} 秒：{} < {}",
+                            self.window, danmu.timeline_s, self.latest
+                        );
+                    }
+                    self.latest = self.latest.max(danmu.timeline_s);
+                    self.heap.push(Reverse(Item {
+                        key: (FloatOrd(danmu.timeline_s), self.seq),
+                        danmu,
+                    }));
+                    self.seq += 1;
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => self.finished = true,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reorder() {
+        let input = [0.0, 2.0, 1.0, 5.0, 3.5, 10.0, 20.0, 4.0]
+            .into_iter()
+            .map(|timeline_s| {
+                Ok(Danmu {
+                    timeline_s,
+                    ..Default::default()
+                })
+            });
+        let output = Reorder::new(input, 3.0)
+            .map(|d| d.unwrap().timeline_s)
+            .collect::<Vec<_>>();
+        // 4.0 晚到了 16 秒，超出窗口
+        assert_eq!(output, [0.0, 1.0, 2.0, 3.5, 5.0, 10.0, 4.0, 20.0]);
+    }
+}
@@ -0,0 +1,172 @@
+//! SRT 不支持定位和动画，所有弹幕静态显示，用 `{\anN}` 标签大致区分显示的位置
+use crate::writer::{non_empty_lines, static_span, Cues, Timestamp, Writer};
+use crate::{CanvasConfig, Drawable};
+use anyhow::Result;
+use std::io::{BufWriter, Write};
+
+pub struct SrtWriter<W: Write> {
+    f: BufWriter<W>,
+    canvas_config: CanvasConfig,
+    cues: Cues,
+    /// 下一条字幕的序号，从 1 开始
+    index: usize,
+}
+
+impl<W: Write> SrtWriter<W> {
+    pub fn new(f: W, canvas_config: CanvasConfig) -> Result<Self> {
+        Ok(SrtWriter {
+            f: BufWriter::with_capacity(10 << 20, f),
+            canvas_config,
+            cues: Cues::default(),
+            index: 1,
+        })
+    }
+}
+
+impl<W: Write> Writer for SrtWriter<W> {
+    fn write(&mut self, drawable: Drawable) -> Result<()> {
+        let (start, end) = static_span(&drawable, &self.canvas_config);
+        // 和 ASS 中 style 的 alignment 对应，滚动弹幕放在顶部，避免挡住正常字幕
+        let align = match drawable.style_name {
+            "Float" | "Top" => "{\\an8}",
+            "Advanced" => "{\\an7}",
+            "SuperChat" => "{\\an1}",
+            "Command" => "{\\an9}",
+            "Gift" => "{\\an3}",
+            _ => "",
+        };
+        let text = non_empty_lines(&drawable.danmu.content)
+            .map(escape_text)
+            .collect::<Vec<_>>()
+            .join("\n");
+        if text.is_empty() {
+            return Ok(());
+        }
+        let (r, g, b) = drawable.danmu.rgb;
+        let text = if (r, g, b) == (0xFF, 0xFF, 0xFF) {
+            text
+        } else {
+            format!("<font color=\"#{r:02x}{g:02x}{b:02x}\">{text}</font>")
+        };
+        self.cues.push(start, end, format!("{align}{text}"));
+        Ok(())
+    }
+
+    fn advance(&mut self, now: f64) -> Result<()> {
+        self.write_cues(now)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_cues(f64::INFINITY)?;
+        self.f.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> SrtWriter<W> {
+    /// 按开始时间写入开始时间早于 `before` 的字幕
+    fn write_cues(&mut self, before: f64) -> Result<()> {
+        while let Some((start, end, text)) = self.cues.pop_before(before) {
+            write!(
+                self.f,
+                "{index}\n{start} --> {end}\n{text}\n\n",
+                index = self.index,
+                start = Timestamp { t: start, sep: ',' },
+                end = Timestamp { t: end, sep: ',' },
+            )?;
+            self.index += 1;
+        }
+        Ok(())
+    }
+}
+
+/// SRT 没有转义，把会被播放器当作标签的 `<>{}` 换成全角字符，避免弹幕内容改变格式
+fn escape_text(text: &str) -> String {
+    text.replace('<', "＜")
+        .replace('>', "＞")
+        .replace('{', "｛")
+        .replace('}', "｝")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Danmu, DrawEffect};
+
+    #[test]
+    fn write_srt() {
+        let mut output = vec![];
+        let mut writer = SrtWriter::new(&mut output, crate::canvas::tests::config()).unwrap();
+        writer
+            .write(Drawable::new(
+                Danmu {
+                    timeline_s: 1.0,
+                    content: "顶部\n\n弹幕".to_string(),
+                    rgb: (0xFF, 0, 0),
+                    ..Default::default()
+                },
+                5.0,
+                "Top",
+                DrawEffect::Fixed { pos: (640, 0) },
+            ))
+            .unwrap();
+        writer
+            .write(Drawable::new(
+                Danmu {
+                    timeline_s: 0.5,
+                    content: "<i>底部{\\an5}</i>".to_string(),
+                    rgb: (0xFF, 0xFF, 0xFF),
+                    ..Default::default()
+                },
+                5.0,
+                "Bottom",
+                DrawEffect::Fixed { pos: (640, 720) },
+            ))
+            .unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        // 按开始时间排序，弹幕中的标签被转义
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "1\n00:00:00,500 --> 00:00:05,500\n＜i＞底部｛\\an5｝＜/i＞\n\n\
+             2\n00:00:01,000 --> 00:00:06,000\n{\\an8}<font color=\"#ff0000\">顶部\n弹幕</font>\n\n"
+        );
+    }
+
+    #[test]
+    fn write_incrementally() {
+        let mut output = vec![];
+        let mut writer = SrtWriter::new(&mut output, crate::canvas::tests::config()).unwrap();
+        let mut max_buffered = 0;
+        for i in 0..1000 {
+            let now = i as f64 * 0.5;
+            writer.advance(now).unwrap();
+            // 被延迟的弹幕开始时间晚于绘制时间
+            let delay = (i % 3) as f64 * 0.7;
+            writer
+                .write(Drawable::new(
+                    Danmu {
+                        timeline_s: now + delay,
+                        content: i.to_string(),
+                        ..Default::default()
+                    },
+                    5.0,
+                    "Top",
+                    DrawEffect::Fixed { pos: (640, 0) },
+                ))
+                .unwrap();
+            max_buffered = max_buffered.max(writer.cues.len());
+        }
+        assert!(max_buffered <= 4, "{max_buffered}");
+        writer.flush().unwrap();
+        drop(writer);
+
+        let output = String::from_utf8(output).unwrap();
+        let starts: Vec<&str> = output
+            .lines()
+            .filter_map(|l| l.split_once(" --> ").map(|(start, _)| start))
+            .collect();
+        assert_eq!(starts.len(), 1000);
+        assert!(starts.windows(2).all(|w| w[0] <= w[1]));
+    }
+}
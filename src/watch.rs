@@ -0,0 +1,258 @@
+//! 监控文件夹，在弹幕文件写完之后进行转换
+use crate::FileFormat;
+use anyhow::Result;
+use notify::{
+    event::{AccessKind, AccessMode},
+    Event, EventKind, RecursiveMode, Watcher,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// 监控 `folder` 下的所有弹幕文件，文件写完后在后台线程中调用 `on_ready`，直到收到退出信号
+///
+/// 文件关闭写入（仅部分平台支持）或者超过 `delay` 没有变化，并且文件已经完整时，认为文件已经写完。
+/// 收到退出信号后会等待正在进行的转换完成。
+pub async fn watch_folder<F>(folder: &Path, delay: Duration, on_ready: F) -> Result<()>
+where
+    F: Fn(&Path) + Send + Sync + 'static,
+{
+    let on_ready = Arc::new(on_ready);
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res| {
+        let _ = tx.send(res);
+    })?;
+    watcher.watch(folder, RecursiveMode::Recursive)?;
+    info!("开始监控目录 {}", folder.display());
+
+    // 文件 => 最后一次变化的时间
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    // 正在转换的文件
+    let mut running: HashSet<PathBuf> = HashSet::new();
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => {
+                info!("收到退出信号，停止监控");
+                break;
+            }
+            event = rx.recv() => {
+                let Some(event) = event else {
+                    anyhow::bail!("文件监控意外退出");
+                };
+                match event {
+                    Ok(event) => handle_event(event, delay, &mut pending),
+                    Err(e) => warn!("文件监控错误：{:?}", e),
+                }
+            }
+            Some(done) = tasks.join_next() => {
+                match done {
+                    Ok(path) => {
+                        running.remove(&path);
+                    }
+                    Err(e) => error!("转换任务异常退出：{:?}", e),
+                }
+            }
+            _ = ticker.tick() => {
+                for path in take_ready(&mut pending, delay, Instant::now()) {
+                    if running.contains(&path) {
+                        // 上一次转换还没有结束，稍后再处理
+                        pending.insert(path, Instant::now());
+                        continue;
+                    }
+                    match is_complete(&path) {
+                        Ok(true) if !matches!(FileFormat::probe(&path), Ok(Some(_))) => {
+                            debug!("文件 {} 不是弹幕文件，跳过", path.display());
+                        }
+                        Ok(true) => {
+                            running.insert(path.clone());
+                            let on_ready = on_ready.clone();
+                            tasks.spawn_blocking(move || {
+                                on_ready(&path);
+                                path
+                            });
+                        }
+                        Ok(false) => debug!("文件 {} 还没有写完，等待下一次变化", path.display()),
+                        Err(e) => debug!("读取文件 {} 失败：{:?}", path.display(), e),
+                    }
+                }
+            }
+        }
+    }
+    if !tasks.is_empty() {
+        info!("等待 {} 个文件转换完成", tasks.len());
+    }
+    while let Some(done) = tasks.join_next().await {
+        if let Err(e) = done {
+            error!("转换任务异常退出：{:?}", e);
+        }
+    }
+    Ok(())
+}
+
+/// 取出超过 `delay` 没有变化的文件
+fn take_ready(
+    pending: &mut HashMap<PathBuf, Instant>,
+    delay: Duration,
+    now: Instant,
+) -> Vec<PathBuf> {
+    let ready: Vec<PathBuf> = pending
+        .iter()
+        .filter(|(_, t)| now.saturating_duration_since(**t) >= delay)
+        .map(|(path, _)| path.clone())
+        .collect();
+    for path in &ready {
+        pending.remove(path);
+    }
+    ready
+}
+
+fn handle_event(event: Event, delay: Duration, pending: &mut HashMap<PathBuf, Instant>) {
+    let paths = event
+        .paths
+        .into_iter()
+        .filter(|p| FileFormat::from_extension(p).is_some());
+    match event.kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+            // 文件已经关闭，不需要再等
+            let now = Instant::now();
+            let ready_at = now.checked_sub(delay).unwrap_or(now);
+            pending.extend(paths.map(|p| (p, ready_at)));
+        }
+        EventKind::Create(_) | EventKind::Modify(_) => {
+            pending.extend(paths.map(|p| (p, Instant::now())));
+        }
+        EventKind::Remove(_) => {
+            for path in paths {
+                pending.remove(&path);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_complete(path: &Path) -> Result<bool> {
+    let Some(format) = FileFormat::from_extension(path) else {
+        return Ok(false);
+    };
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(64)))?;
+    let mut tail = vec![];
+    file.read_to_end(&mut tail)?;
+    Ok(is_complete_tail(format, &tail))
+}
+
+/// 根据文件末尾判断文件是否已经写完
+fn is_complete_tail(format: FileFormat, tail: &[u8]) -> bool {
+    let tail = tail.trim_ascii_end();
+    match format {
+        // 录播姬在结束录制的时候才会写入 `</i>`
+        FileFormat::Xml => tail.ends_with(b"</i>"),
+        FileFormat::Json => tail.ends_with(b"]") || tail.ends_with(b"}"),
+        // protobuf 没有结束标记，只能依靠文件不再变化
+        FileFormat::Protobuf => !tail.is_empty(),
+    }
+}
+
+pub(crate) async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(e) => {
+                warn!("无法监听 SIGTERM：{:?}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, ModifyKind};
+
+    #[test]
+    fn complete() {
+        assert!(is_complete_tail(
+            FileFormat::Xml,
+            b"<d p=\"1\">a</d>\r\n</i>\r\n"
+        ));
+        assert!(!is_complete_tail(FileFormat::Xml, b"<d p=\"1\">a</d>\n"));
+        assert!(is_complete_tail(FileFormat::Json, b"{\"id\": 1}]\n"));
+        assert!(!is_complete_tail(FileFormat::Json, b"[{\"id\": 1},"));
+        assert!(is_complete_tail(FileFormat::Protobuf, &[0x0a, 0x02]));
+        assert!(!is_complete_tail(FileFormat::Protobuf, b""));
+    }
+
+    #[test]
+    fn debounce() {
+        let delay = Duration::from_secs(5);
+        let mut pending = HashMap::new();
+        let event = |kind, path: &str| Event::new(kind).add_path(PathBuf::from(path));
+
+        handle_event(
+            event(EventKind::Create(CreateKind::File), "a.xml"),
+            delay,
+            &mut pending,
+        );
+        handle_event(
+            event(EventKind::Create(CreateKind::File), "config.toml"),
+            delay,
+            &mut pending,
+        );
+        handle_event(
+            event(EventKind::Modify(ModifyKind::Any), "b.json"),
+            delay,
+            &mut pending,
+        );
+        assert_eq!(pending.len(), 2);
+        let now = Instant::now();
+        assert!(take_ready(&mut pending, delay, now).is_empty());
+
+        // 关闭写入的文件马上可以处理
+        handle_event(
+            event(
+                EventKind::Access(AccessKind::Close(AccessMode::Write)),
+                "c.so",
+            ),
+            delay,
+            &mut pending,
+        );
+        assert_eq!(
+            take_ready(&mut pending, delay, Instant::now()),
+            vec![PathBuf::from("c.so")]
+        );
+
+        // 删除的文件不再处理
+        handle_event(
+            event(EventKind::Remove(notify::event::RemoveKind::File), "b.json"),
+            delay,
+            &mut pending,
+        );
+        assert_eq!(
+            take_ready(&mut pending, delay, now + delay),
+            vec![PathBuf::from("a.xml")]
+        );
+        assert!(pending.is_empty());
+    }
+}
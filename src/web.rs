@@ -1,9 +1,9 @@
-use std::collections::HashSet;
-
 use actix_web::{web, HttpResponse};
 use anyhow::{bail, Context};
-use biliapi::Request;
-use danmu2ass::{bilibili::DanmakuElem, CanvasConfig, InputType};
+use danmu2ass::{
+    bilibili::{ClientConfig, DanmakuElem},
+    CanvasConfig, FileFormat, Filter, InputType, OutputFormat,
+};
 use log::info;
 use serde::Deserialize;
 use serde_json::json;
@@ -19,22 +19,45 @@ pub enum Source {
 pub struct ConvertRequest {
     source: Source,
     config: CanvasConfig,
-    denylist: Option<HashSet<String>>,
+    /// 过滤规则，每一项为规则文件中的一行
+    denylist: Option<Vec<String>>,
+    #[serde(default)]
+    format: OutputFormat,
+    /// 不转换，直接下载获取到的弹幕的 XML，方便存档以及之后用不同的设置重新转换
+    #[serde(default)]
+    save_xml: bool,
 }
 
-async fn convert(request: web::Json<ConvertRequest>) -> HttpResponse {
+async fn convert(
+    request: web::Json<ConvertRequest>,
+    client_config: web::Data<ClientConfig>,
+) -> HttpResponse {
     let req = request.into_inner();
+    let filter = match req
+        .denylist
+        .as_ref()
+        .map(|l| Filter::new(l.iter().map(String::as_str)))
+    {
+        None => None,
+        Some(Ok(filter)) => Some(filter),
+        Some(Err(e)) => {
+            return HttpResponse::BadRequest().json(json!({
+                "errmsg": format!("{e:#?}")
+            }));
+        }
+    };
     let mut output = Vec::<u8>::new();
     let title = match req.source {
         Source::Xml { content, title } => {
             info!("parsing {} bytes in xml", content.len());
-            let parser = danmu2ass::Parser::new(content.as_bytes());
+            let parser = danmu2ass::Parser::new(content.as_bytes()).parse_gifts(req.config.gift);
             let r = danmu2ass::convert(
                 parser,
                 title.clone(),
                 &mut output,
+                req.format,
                 req.config,
-                &req.denylist,
+                &filter,
             );
             if let Err(e) = r {
                 return HttpResponse::BadRequest().json(json!({
@@ -48,9 +71,26 @@ async fn convert(request: web::Json<ConvertRequest>) -> HttpResponse {
                 .unwrap_or(title);
             ass_title
         }
+        Source::Url { url } if req.save_xml => {
+            let input_type: InputType = url.parse().unwrap();
+            let r = fetch_danmaku(input_type, &client_config)
+                .await
+                .and_then(|(title, danmu)| {
+                    danmu2ass::bilibili::write_xml(&mut output, &danmu)?;
+                    Ok(title)
+                });
+            match r {
+                Ok(title) => return attachment(&title, "xml", output),
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(json!({
+                        "errmsg": format!("{e:#?}")
+                    }));
+                }
+            }
+        }
         Source::Url { url } => {
             let input_type: InputType = url.parse().unwrap();
-            let r = run_input_type(input_type).await;
+            let r = run_input_type(input_type, &client_config).await;
             let (title, danmu) = match r {
                 Ok((title, danmu)) => (title, danmu),
                 Err(e) => {
@@ -60,8 +100,14 @@ async fn convert(request: web::Json<ConvertRequest>) -> HttpResponse {
                 }
             };
             log::info!("danmu downloaded, title={}", title);
-            let r =
-                danmu2ass::convert(danmu, title.clone(), &mut output, req.config, &req.denylist);
+            let r = danmu2ass::convert(
+                danmu,
+                title.clone(),
+                &mut output,
+                req.format,
+                req.config,
+                &filter,
+            );
             if let Err(e) = r {
                 return HttpResponse::BadRequest().json(json!({
                     "errmsg": format!("{e:#?}")
@@ -70,43 +116,67 @@ async fn convert(request: web::Json<ConvertRequest>) -> HttpResponse {
             title
         }
     };
+    attachment(&title, req.format.extension(), output)
+}
+
+fn attachment(title: &str, extension: &str, body: Vec<u8>) -> HttpResponse {
     let title =
         percent_encoding::percent_encode(title.as_bytes(), percent_encoding::NON_ALPHANUMERIC);
-    let content_disposition = format!("attachment; filename=\"{title}.ass\"");
+    let content_disposition = format!("attachment; filename=\"{title}.{extension}\"");
     HttpResponse::Ok()
         .append_header(("Content-Type", "text/plain; charset=utf-8"))
         .append_header(("Content-Disposition", content_disposition))
-        .body(output)
+        .body(body)
 }
 
 type Iter = Box<dyn Iterator<Item = anyhow::Result<danmu2ass::Danmu>>>;
 
-async fn run_input_type(input_type: InputType) -> anyhow::Result<(String, Iter)> {
-    let client = biliapi::connection::new_client()?;
+async fn run_input_type(
+    input_type: InputType,
+    client_config: &ClientConfig,
+) -> anyhow::Result<(String, Iter)> {
     match input_type {
         InputType::File(path) => {
-            let file = std::fs::File::open(&path)?;
-            let parser = danmu2ass::Parser::new(std::io::BufReader::with_capacity(1 << 20, file));
+            let danmu = FileFormat::detect(&path)?.open(&path, false)?;
             let filename = path
                 .file_name()
                 .and_then(|s| s.to_str())
                 .unwrap_or("danmu")
                 .to_string();
-            Ok((filename, Box::new(parser)))
+            Ok((filename, danmu))
         }
+        input_type => {
+            let (title, danmu) = fetch_danmaku(input_type, client_config).await?;
+            let danmu = danmu.into_iter().map(|i| Ok(i.into()));
+            Ok((title, Box::new(danmu)))
+        }
+    }
+}
+
+/// 下载哔哩哔哩视频或者番剧的弹幕，使用启动时命令行和配置文件中的接口配置和登录凭据
+async fn fetch_danmaku(
+    input_type: InputType,
+    client_config: &ClientConfig,
+) -> anyhow::Result<(String, Vec<DanmakuElem>)> {
+    let client = danmu2ass::bilibili::Client::new(client_config.clone())?;
+    let (title, danmu) = match input_type.resolve(&client).await? {
         InputType::BV { bv, p } => {
-            let p = p.unwrap_or(1);
+            // web 只返回一个文件，指定了多个分 p 时使用第一个
+            let p = p.map_or(1, |p| *p.start());
             // get info for video
-            let mut info = biliapi::requests::VideoInfo::request(&client, bv.clone()).await?;
+            let mut info = client.video_info(&bv).await?;
             if p > info.pages.len() as u32 {
                 anyhow::bail!("视频 {} 只有 {} p，指定 {}p", bv, info.pages.len(), p);
             }
             let page = info.pages.swap_remove(p as usize - 1);
 
-            let danmu =
-                danmu2ass::bilibili::get_danmu_for_video(page.cid, page.duration.as_secs()).await?;
-            let danmu = danmu.into_iter().map(|i| Ok(i.into()));
-            Ok((info.title, Box::new(danmu)))
+            let danmu = danmu2ass::bilibili::get_danmu_for_video(
+                &client,
+                page.cid,
+                page.duration.as_secs(),
+            )
+            .await?;
+            (info.title, danmu)
         }
         InputType::Season { season_id } => {
             let mut season_info =
@@ -115,11 +185,13 @@ async fn run_input_type(input_type: InputType) -> anyhow::Result<(String, Iter)>
                     .context("获取 season 失败")?;
             let title = season_info.title;
             let episode = season_info.episodes.swap_remove(0);
-            let danmu =
-                danmu2ass::bilibili::get_danmu_for_video(episode.cid, episode.duration_ms / 1000)
-                    .await?;
-            let danmu = danmu.into_iter().map(|i| Ok(i.into()));
-            Ok((title, Box::new(danmu)))
+            let danmu = danmu2ass::bilibili::get_danmu_for_video(
+                &client,
+                episode.cid,
+                episode.duration_ms / 1000,
+            )
+            .await?;
+            (title, danmu)
         }
         InputType::Episode { episode_id } => {
             let season_info = danmu2ass::bilibili::Season::request(&client, ("ep_id", episode_id))
@@ -132,14 +204,22 @@ async fn run_input_type(input_type: InputType) -> anyhow::Result<(String, Iter)>
                 .ok_or_else(|| anyhow::anyhow!("没有找到 ep_id {}", episode_id))?;
             let title = format!("{} - {}", season_info.title, ep.title);
             let danmu =
-                danmu2ass::bilibili::get_danmu_for_video(ep.cid, ep.duration_ms / 1000).await?;
-            let danmu = danmu.into_iter().map(|i| Ok(i.into()));
-            Ok((title, Box::new(danmu)))
+                danmu2ass::bilibili::get_danmu_for_video(&client, ep.cid, ep.duration_ms / 1000)
+                    .await?;
+            (title, danmu)
         }
         _ => {
             bail!("Unsupported input type");
         }
+    };
+    if !danmu.is_complete() {
+        log::warn!(
+            "{} 有 {} 个分段下载失败，弹幕不完整",
+            title,
+            danmu.failed_segments.len()
+        );
     }
+    Ok((title, danmu.elems))
 }
 
 fn files_service() -> actix_files::Files {
@@ -148,7 +228,11 @@ fn files_service() -> actix_files::Files {
         .prefer_utf8(true)
 }
 
-pub async fn run_server() -> anyhow::Result<()> {
+pub async fn run_server(client_config: ClientConfig) -> anyhow::Result<()> {
+    if client_config.credential.is_some() {
+        info!("下载弹幕时使用登录凭据");
+    }
+    let client_config = web::Data::new(client_config);
     let port = if portpicker::is_free(8081) {
         8081
     } else {
@@ -157,6 +241,7 @@ pub async fn run_server() -> anyhow::Result<()> {
     let fut = actix_web::HttpServer::new(move || {
         actix_web::App::new()
             // .wrap(actix_cors::Cors::permissive())
+            .app_data(client_config.clone())
             .route("/convert", web::post().to(convert))
             .default_service(files_service())
     })
@@ -0,0 +1,170 @@
+//! WebVTT 不支持动画，弹幕静态显示在画布上对应的行，通过 cue settings 定位
+use crate::writer::{non_empty_lines, static_span, Cues, Timestamp, Writer};
+use crate::{CanvasConfig, DrawEffect, Drawable};
+use anyhow::Result;
+use std::io::{BufWriter, Write};
+
+pub struct WebVttWriter<W: Write> {
+    f: BufWriter<W>,
+    canvas_config: CanvasConfig,
+    cues: Cues,
+}
+
+impl<W: Write> WebVttWriter<W> {
+    pub fn new(f: W, title: String, canvas_config: CanvasConfig) -> Result<Self> {
+        let mut this = WebVttWriter {
+            f: BufWriter::with_capacity(10 << 20, f),
+            canvas_config,
+            cues: Cues::default(),
+        };
+        // 标题写在头部同一行，不能包含换行和 -->
+        let title = title.replace(['\r', '\n'], " ").replace("-->", "->");
+        write!(this.f, "WEBVTT - {title}\n\n")?;
+        Ok(this)
+    }
+
+    /// 根据画布上的坐标计算 cue settings，对齐方式和 ASS 中的 style 一致
+    fn cue_settings(&self, drawable: &Drawable) -> String {
+        let x = |v: i32| percent(v, self.canvas_config.width);
+        let y = |v: i32| percent(v, self.canvas_config.height);
+        match (&drawable.effect, drawable.style_name) {
+            (DrawEffect::Move { start: (_, y0), .. }, _) => {
+                format!("line:{:.2}% position:50% align:center", y(*y0))
+            }
+            (
+                DrawEffect::Boxed {
+                    pos, size, padding, ..
+                },
+                _,
+            ) => format!(
+                "line:{:.2}% position:{:.2}%,line-left size:{:.2}% align:start",
+                y(pos.1 + padding),
+                x(pos.0 + padding),
+                x(size.0 - 2 * padding),
+            ),
+            (DrawEffect::Fixed { pos }, "Bottom" | "Subtitle") => format!(
+                "line:{:.2}%,end position:{:.2}% align:center",
+                y(pos.1),
+                x(pos.0)
+            ),
+            (DrawEffect::Fixed { pos }, "Gift") => format!(
+                "line:{:.2}%,end position:{:.2}%,line-right align:end",
+                y(pos.1),
+                x(pos.0)
+            ),
+            (DrawEffect::Advanced { start, .. }, _) => format!(
+                "line:{:.2}% position:{:.2}%,line-left align:start",
+                y(start.1),
+                x(start.0)
+            ),
+            (DrawEffect::Fixed { pos }, _) => format!(
+                "line:{:.2}% position:{:.2}% align:center",
+                y(pos.1),
+                x(pos.0)
+            ),
+        }
+    }
+}
+
+impl<W: Write> Writer for WebVttWriter<W> {
+    fn write(&mut self, drawable: Drawable) -> Result<()> {
+        let (start, end) = static_span(&drawable, &self.canvas_config);
+        let text = non_empty_lines(&drawable.danmu.content)
+            .map(escape_text)
+            .collect::<Vec<_>>()
+            .join("\n");
+        if text.is_empty() {
+            return Ok(());
+        }
+        let settings = self.cue_settings(&drawable);
+        self.cues.push(start, end, format!("{settings}\n{text}"));
+        Ok(())
+    }
+
+    fn advance(&mut self, now: f64) -> Result<()> {
+        self.write_cues(now)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_cues(f64::INFINITY)?;
+        self.f.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> WebVttWriter<W> {
+    /// WebVTT 要求字幕按开始时间排序，写入开始时间早于 `before` 的字幕
+    fn write_cues(&mut self, before: f64) -> Result<()> {
+        while let Some((start, end, cue)) = self.cues.pop_before(before) {
+            write!(
+                self.f,
+                "{start} --> {end} {cue}\n\n",
+                start = Timestamp { t: start, sep: '.' },
+                end = Timestamp { t: end, sep: '.' },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn percent(v: i32, total: u32) -> f64 {
+    (v as f64 * 100.0 / total as f64).clamp(0.0, 100.0)
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Danmu;
+
+    #[test]
+    fn write_vtt() {
+        let mut output = vec![];
+        let mut writer = WebVttWriter::new(
+            &mut output,
+            "标题".to_string(),
+            crate::canvas::tests::config(),
+        )
+        .unwrap();
+        writer
+            .write(Drawable::new(
+                Danmu {
+                    timeline_s: 10.0,
+                    content: "<滚动>".to_string(),
+                    ..Default::default()
+                },
+                15.0,
+                "Float",
+                DrawEffect::Move {
+                    start: (1280, 72),
+                    end: (-220, 72),
+                },
+            ))
+            .unwrap();
+        writer
+            .write(Drawable::new(
+                Danmu {
+                    timeline_s: 1.0,
+                    content: "底部".to_string(),
+                    ..Default::default()
+                },
+                5.0,
+                "Bottom",
+                DrawEffect::Fixed { pos: (640, 648) },
+            ))
+            .unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "WEBVTT - 标题\n\n\
+             00:00:01.000 --> 00:00:06.000 line:90.00%,end position:50.00% align:center\n底部\n\n\
+             00:00:10.000 --> 00:00:18.600 line:10.00% position:50% align:center\n&lt;滚动&gt;\n\n"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! 输出格式，ASS 之外的字幕格式不支持动画，只能用静态的方式近似
+use crate::{AssWriter, CanvasConfig, DrawEffect, Drawable, SrtWriter, WebVttWriter};
+use anyhow::Result;
+use float_ord::FloatOrd;
+use std::{cmp::Reverse, collections::BinaryHeap, fmt, io::Write};
+
+/// 将绘制好的弹幕写入字幕文件
+pub trait Writer {
+    fn write(&mut self, drawable: Drawable) -> Result<()>;
+
+    /// 绘制进行到了 `now`（弹幕原本的时间），之后绘制的弹幕开始时间都不会早于 `now`，
+    /// 需要排序的格式可以写入之前缓存的字幕
+    fn advance(&mut self, _now: f64) -> Result<()> {
+        Ok(())
+    }
+
+    /// 写入缓冲区中剩余的内容
+    fn flush(&mut self) -> Result<()>;
+}
+
+/// 输出的字幕格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// 完整支持滚动、定位和颜色
+    #[default]
+    Ass,
+    /// 只有静态文字，大部分播放器支持 `{\an8}` 定位和 `<font>` 颜色
+    Srt,
+    /// 静态文字，按照弹幕所在的行定位
+    Vtt,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Ass => "ass",
+            OutputFormat::Srt => "srt",
+            OutputFormat::Vtt => "vtt",
+        }
+    }
+
+    /// 在 `output` 上创建对应格式的 writer，交给 `f` 使用
+    pub fn with_writer<O, F, T>(
+        &self,
+        output: O,
+        title: String,
+        canvas_config: CanvasConfig,
+        f: F,
+    ) -> Result<T>
+    where
+        O: Write,
+        F: FnOnce(&mut dyn Writer) -> Result<T>,
+    {
+        match self {
+            OutputFormat::Ass => run(AssWriter::new(output, title, canvas_config)?, f),
+            OutputFormat::Srt => run(SrtWriter::new(output, canvas_config)?, f),
+            OutputFormat::Vtt => run(WebVttWriter::new(output, title, canvas_config)?, f),
+        }
+    }
+}
+
+fn run<W, F, T>(mut writer: W, f: F) -> Result<T>
+where
+    W: Writer,
+    F: FnOnce(&mut dyn Writer) -> Result<T>,
+{
+    let ret = f(&mut writer)?;
+    writer.flush()?;
+    Ok(ret)
+}
+
+/// 静态格式中弹幕显示的时间段。
+///
+/// 滚动弹幕从出现开始显示，直到弹幕的尾部经过屏幕中线，至少显示 `fixed_duration` 秒，
+/// 避免很短的弹幕一闪而过
+pub(crate) fn static_span(drawable: &Drawable, config: &CanvasConfig) -> (f64, f64) {
+    let start = drawable.danmu.timeline_s;
+    match drawable.effect {
+        DrawEffect::Move {
+            start: (x0, _),
+            end: (x1, _),
+        } => {
+            // 移动距离为屏幕宽度加上弹幕长度
+            let distance = (x1 - x0).abs() as f64;
+            let speed = distance / drawable.duration;
+            let half = config.width as f64 / 2.0;
+            let length = distance - config.width as f64;
+            let end = start + (half + length) / speed;
+            (start, end.max(start + config.fixed_duration))
+        }
+        DrawEffect::Boxed { .. } | DrawEffect::Fixed { .. } | DrawEffect::Advanced { .. } => {
+            (start, start + drawable.duration)
+        }
+    }
+}
+
+/// (开始时间, 绘制顺序, 结束时间, 字幕内容)，开始时间相同的保持绘制的顺序
+type Cue = (FloatOrd<f64>, usize, FloatOrd<f64>, String);
+
+/// 静态格式要求字幕按开始时间排序，而绘制的顺序不一定按开始时间（如被延迟的弹幕）。
+///
+/// 弹幕只会被延迟，不会提前，所以开始时间早于当前绘制时间的字幕都可以写入了，
+/// 缓存中只保留最大延迟时间内的字幕
+#[derive(Default)]
+pub(crate) struct Cues {
+    heap: BinaryHeap<Reverse<Cue>>,
+    seq: usize,
+}
+impl Cues {
+    pub fn push(&mut self, start: f64, end: f64, text: String) {
+        self.heap
+            .push(Reverse((FloatOrd(start), self.seq, FloatOrd(end), text)));
+        self.seq += 1;
+    }
+
+    /// 取出开始时间早于 `before` 的第一条字幕，返回 (开始时间, 结束时间, 字幕内容)
+    pub fn pop_before(&mut self, before: f64) -> Option<(f64, f64, String)> {
+        let Reverse((FloatOrd(start), ..)) = self.heap.peek()?;
+        if *start >= before {
+            return None;
+        }
+        let Reverse((FloatOrd(start), _, FloatOrd(end), text)) = self.heap.pop()?;
+        Some((start, end, text))
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+}
+
+/// `HH:MM:SS,mmm` 格式的时间，SRT 使用 `,` 分隔毫秒，WebVTT 使用 `.`
+pub(crate) struct Timestamp {
+    pub t: f64,
+    pub sep: char,
+}
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = (self.t.max(0.0) * 1000.0).round() as u64;
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:03}",
+            ms / 3_600_000,
+            ms / 60_000 % 60,
+            ms / 1000 % 60,
+            self.sep,
+            ms % 1000
+        )
+    }
+}
+
+/// 去掉空行，两种格式都用空行分隔字幕
+pub(crate) fn non_empty_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().map(str::trim).filter(|l| !l.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamp_fmt() {
+        let ts = |t| Timestamp { t, sep: ',' }.to_string();
+        assert_eq!(ts(0.0), "00:00:00,000");
+        assert_eq!(ts(1.5), "00:00:01,500");
+        assert_eq!(ts(3600.0 + 60.0 + 1.0 + 0.0126), "01:01:01,013");
+        assert_eq!(
+            Timestamp {
+                t: 59.9999,
+                sep: '.'
+            }
+            .to_string(),
+            "00:01:00.000"
+        );
+    }
+
+    #[test]
+    fn float_span_at_center() {
+        let config = crate::canvas::tests::config();
+        // 长度 220 的弹幕，15 秒从 1280 移动到 -220
+        let drawable = Drawable::new(
+            crate::Danmu {
+                timeline_s: 10.0,
+                ..Default::default()
+            },
+            15.0,
+            "Float",
+            DrawEffect::Move {
+                start: (1280, 0),
+                end: (-220, 0),
+            },
+        );
+        let (start, end) = static_span(&drawable, &config);
+        assert_eq!(start, 10.0);
+        assert!((end - 18.6).abs() < 1e-9);
+
+        // 滚动很快的弹幕至少显示 fixed_duration 秒
+        let drawable = Drawable::new(
+            crate::Danmu {
+                timeline_s: 10.0,
+                ..Default::default()
+            },
+            4.0,
+            "Float",
+            DrawEffect::Move {
+                start: (1280, 0),
+                end: (-20, 0),
+            },
+        );
+        assert_eq!(static_span(&drawable, &config), (10.0, 15.0));
+    }
+}
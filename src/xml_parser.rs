@@ -1,4 +1,4 @@
-use super::danmu::{Danmu, DanmuType};
+use super::danmu::{Danmu, DanmuType, Pool};
 use anyhow::{bail, Context, Result};
 use std::{
     fs::File,
@@ -14,6 +14,7 @@ use xml::reader::EventReader as Reader;
 pub struct Parser<R: BufRead> {
     count: usize,
     reader: Reader<R>,
+    parse_gifts: bool,
     #[cfg(feature = "quick_xml")]
     buf: Vec<u8>,
 }
@@ -28,11 +29,18 @@ impl<R: BufRead> Parser<R> {
         Self {
             count: 0,
             reader,
+            parse_gifts: false,
 
             #[cfg(feature = "quick_xml")]
             buf: Vec::new(),
         }
     }
+
+    /// 是否解析礼物（gift）和上舰（guard），默认不解析
+    pub fn parse_gifts(mut self, parse_gifts: bool) -> Self {
+        self.parse_gifts = parse_gifts;
+        self
+    }
 }
 
 impl Parser<BufReader<File>> {
@@ -54,6 +62,7 @@ impl Parser<BufReader<File>> {
         Ok(Self {
             count: 0,
             reader,
+            parse_gifts: false,
             #[cfg(feature = "quick_xml")]
             buf: Vec::new(),
         })
@@ -97,7 +106,44 @@ impl<R: BufRead> Iterator for Parser<R> {
                         Err(e) => return Some(Err(e)),
                     };
                 }
-                xml::reader::XmlEvent::EndElement { name } if name.local_name == "d" => {
+                xml::reader::XmlEvent::StartElement {
+                    name, attributes, ..
+                } if name.local_name == "sc" => {
+                    let get = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|attr| attr.name.local_name == key)
+                            .map(|attr| attr.value.clone())
+                    };
+                    match Danmu::from_xml_superchat(get).context("醒目留言解析错误") {
+                        Ok(parsed) => {
+                            danmu = parsed;
+                        }
+                        Err(e) => return Some(Err(e)),
+                    };
+                }
+                xml::reader::XmlEvent::StartElement {
+                    name, attributes, ..
+                } if self.parse_gifts
+                    && (name.local_name == "gift" || name.local_name == "guard") =>
+                {
+                    let get = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|attr| attr.name.local_name == key)
+                            .map(|attr| attr.value.clone())
+                    };
+                    let parsed = if name.local_name == "gift" {
+                        Danmu::from_xml_gift(get).context("礼物解析错误")
+                    } else {
+                        Danmu::from_xml_guard(get).context("上舰解析错误")
+                    };
+                    self.count += 1;
+                    return Some(parsed);
+                }
+                xml::reader::XmlEvent::EndElement { name }
+                    if name.local_name == "d" || name.local_name == "sc" =>
+                {
                     self.count += 1;
                     return Some(Ok(danmu));
                 }
@@ -180,15 +226,47 @@ impl<R: BufRead> Iterator for Parser<R> {
                         Err(e) => return Some(Err(e)),
                     };
                 }
-                Event::End(end) if end.local_name().as_ref() == b"d" => match status {
-                    Status::WaitForEnd(danmu) => {
-                        self.count += 1;
-                        return Some(Ok(danmu));
+                Event::Start(start) if start.local_name().as_ref() == b"sc" => {
+                    let get = |key: &str| get_attr(&start, key);
+                    match Danmu::from_xml_superchat(get).context("醒目留言解析错误") {
+                        Ok(parsed) => {
+                            status = Status::AttrWaitForContent(parsed);
+                        }
+                        Err(e) => return Some(Err(e)),
                     }
-                    _ => continue,
-                },
+                }
+                // 没有内容的醒目留言 <sc ... />
+                Event::Empty(start) if start.local_name().as_ref() == b"sc" => {
+                    let get = |key: &str| get_attr(&start, key);
+                    self.count += 1;
+                    return Some(Danmu::from_xml_superchat(get).context("醒目留言解析错误"));
+                }
+                // 礼物和上舰都是自闭合的元素，没有内容
+                Event::Start(start) | Event::Empty(start)
+                    if self.parse_gifts
+                        && matches!(start.local_name().as_ref(), b"gift" | b"guard") =>
+                {
+                    let get = |key: &str| get_attr(&start, key);
+                    let parsed = if start.local_name().as_ref() == b"gift" {
+                        Danmu::from_xml_gift(get).context("礼物解析错误")
+                    } else {
+                        Danmu::from_xml_guard(get).context("上舰解析错误")
+                    };
+                    self.count += 1;
+                    return Some(parsed);
+                }
+                Event::End(end) if matches!(end.local_name().as_ref(), b"d" | b"sc") => {
+                    match status {
+                        // 没有内容的 <sc ...></sc> 也需要输出，内容为空
+                        Status::WaitForEnd(danmu) | Status::AttrWaitForContent(danmu) => {
+                            self.count += 1;
+                            return Some(Ok(danmu));
+                        }
+                        Status::Start => continue,
+                    }
+                }
                 Event::Text(text) => {
-                    let s = match std::str::from_utf8(&text).context("非法 UTF-8 字符") {
+                    let s = match text.unescape().context("弹幕内容解析错误") {
                         #[cfg(debug_assertions)]
                         Ok(s) => format!("{}-{}", self.count, s),
                         #[cfg(not(debug_assertions))]
@@ -222,9 +300,10 @@ impl Danmu {
     /// 3. 字体大小（默认25）
     /// 4. 弹幕颜色（如14893055）
     /// 5. 弹幕毫秒级时间戳（如 1647777083220）
-    /// 6. 0
-    /// 7. 用户 UID（如 398452452）
-    /// 8. 0
+    /// 6. 弹幕池，0 为普通弹幕，1 为字幕弹幕，2 为特殊弹幕
+    /// 7. 用户 UID（如 398452452），哔哩哔哩视频弹幕中为 mid_hash
+    /// 8. 0，哔哩哔哩视频弹幕中为 dmid
+    /// 9. 哔哩哔哩视频弹幕的权重，录播姬的 XML 中没有
     pub fn from_xml_p_attr(p_attr: &str) -> Result<Option<Self>> {
         let mut iter = p_attr.split(',');
         let timeline_s = iter
@@ -265,6 +344,15 @@ impl Danmu {
         } else {
             bail!("颜色解析错误：颜色为 {:x}", rgb);
         };
+        // 跳过发送时间戳
+        iter.next();
+        let pool = Pool::from_num(iter.next().and_then(|s| s.parse().ok()).unwrap_or(0));
+        let sender = iter
+            .next()
+            .filter(|s| !s.is_empty() && *s != "0")
+            .map(ToString::to_string);
+        // 没有第 9 个字段时为 None，不参与智能屏蔽
+        let weight = iter.nth(1).and_then(|s| s.parse().ok());
 
         Ok(Some(Self {
             timeline_s,
@@ -272,9 +360,116 @@ impl Danmu {
             r#type,
             fontsize,
             rgb: (r as u8, g as u8, b as u8),
+            sender,
+            weight,
+            pool,
+            font_scale: None,
         }))
     }
 }
+#[cfg(feature = "quick_xml")]
+fn get_attr(start: &quick_xml::events::BytesStart, key: &str) -> Option<String> {
+    start
+        .attributes()
+        .filter_map(|r| r.ok())
+        .find(|attr| attr.key.as_ref() == key.as_bytes())
+        .and_then(|attr| attr.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+impl Danmu {
+    /// 从录播姬的醒目留言解析
+    ///
+    /// <sc ts="12.345" user="user" uid="123" price="30" time="60"> content </sc>
+    /// 其中 ts 为时间（秒），price 为价格（元），time 为直播间中的显示时长（秒，这里不使用）
+    pub fn from_xml_superchat(get: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let timeline_s = get("ts")
+            .context("醒目留言中没有 ts 属性")?
+            .parse()
+            .context("时间解析错误")?;
+        let user = get("user").unwrap_or_default();
+        let price = get("price")
+            .context("醒目留言中没有 price 属性")?
+            .parse::<f64>()
+            .context("价格解析错误")? as u32;
+        Ok(Self {
+            timeline_s,
+            content: String::new(),
+            r#type: DanmuType::SuperChat { user, price },
+            fontsize: 25,
+            rgb: (0xFF, 0xFF, 0xFF),
+            sender: get("uid"),
+            weight: None,
+            pool: Pool::Normal,
+            font_scale: None,
+        })
+    }
+}
+
+impl Danmu {
+    /// 从录播姬的礼物解析
+    ///
+    /// <gift ts="0.576" user="user" uid="123" giftname="小心心" giftcount="1" />
+    pub fn from_xml_gift(get: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let timeline_s = get("ts")
+            .context("礼物中没有 ts 属性")?
+            .parse()
+            .context("时间解析错误")?;
+        let count = get("giftcount")
+            .context("礼物中没有 giftcount 属性")?
+            .parse()
+            .context("礼物数量解析错误")?;
+        Ok(Self {
+            timeline_s,
+            content: String::new(),
+            r#type: DanmuType::Gift {
+                user: get("user").unwrap_or_default(),
+                gift_name: get("giftname").unwrap_or_default(),
+                count,
+            },
+            fontsize: 25,
+            rgb: (0xFF, 0xFF, 0xFF),
+            sender: get("uid"),
+            weight: None,
+            pool: Pool::Normal,
+            font_scale: None,
+        })
+    }
+
+    /// 从录播姬的上舰解析
+    ///
+    /// <guard ts="12.345" user="user" uid="123" level="3" count="1" />
+    pub fn from_xml_guard(get: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let timeline_s = get("ts")
+            .context("上舰中没有 ts 属性")?
+            .parse()
+            .context("时间解析错误")?;
+        let level = get("level")
+            .context("上舰中没有 level 属性")?
+            .parse()
+            .context("舰长等级解析错误")?;
+        let count = get("count")
+            .context("上舰中没有 count 属性")?
+            .parse()
+            .context("上舰数量解析错误")?;
+        Ok(Self {
+            timeline_s,
+            content: String::new(),
+            r#type: DanmuType::Guard {
+                user: get("user").unwrap_or_default(),
+                level,
+                count,
+            },
+            fontsize: 25,
+            rgb: (0xFF, 0xFF, 0xFF),
+            sender: get("uid"),
+            weight: None,
+            pool: Pool::Normal,
+            font_scale: None,
+        })
+    }
+}
+
 impl DanmuType {
     pub fn from_xml_num(num: u32) -> Result<Self> {
         Ok(match num {
@@ -282,6 +477,7 @@ impl DanmuType {
             4 => DanmuType::Bottom,
             5 => DanmuType::Top,
             6 => DanmuType::Reverse,
+            7 => DanmuType::Advanced,
             _ => bail!("未知的弹幕类型：{}", num),
         })
     }
@@ -353,6 +549,10 @@ mod tests {
                 r#type: DanmuType::Float,
                 fontsize: 25,
                 rgb: (0xe3, 0x3f, 0xff),
+                sender: Some("398452452".to_string()),
+                weight: None,
+                pool: Pool::Normal,
+                font_scale: None,
             }
         );
     }
@@ -370,8 +570,32 @@ mod tests {
                 r#type: DanmuType::Float,
                 fontsize: 25,
                 rgb: (0xe3, 0x3f, 0xff),
+                sender: Some("215087720".to_string()),
+                weight: None,
+                pool: Pool::Normal,
+                font_scale: None,
             }
         );
+
+        // 哔哩哔哩视频弹幕的 XML 中第 9 个字段为权重
+        let danmu = Danmu::from_xml_p_attr("1.5,1,25,16777215,1647777083,0,1537d7c7,1234567890,8")
+            .unwrap()
+            .unwrap();
+        assert_eq!(danmu.sender.as_deref(), Some("1537d7c7"));
+        assert_eq!(danmu.weight, Some(8));
+        assert_eq!(danmu.pool, Pool::Normal);
+
+        let danmu = Danmu::from_xml_p_attr("1.5,1,25,16777215,1647777083,1,1537d7c7,1234567890")
+            .unwrap()
+            .unwrap();
+        assert_eq!(danmu.pool, Pool::Subtitle);
+        assert_eq!(danmu.weight, None);
+
+        // --save-xml 保存的权重 0 读回来也是 0
+        let danmu = Danmu::from_xml_p_attr("1.5,1,25,16777215,1647777083,0,1537d7c7,1234567890,0")
+            .unwrap()
+            .unwrap();
+        assert_eq!(danmu.weight, Some(0));
     }
 
     #[test]
@@ -381,6 +605,76 @@ mod tests {
         assert_eq!(danmu.content, "0-呵\n呵\n比\n你\n们\n更\n喜\n欢\n晚\n晚");
     }
 
+    #[test]
+    fn parse_superchat() {
+        static SUPER_CHAT: &str = r#"
+        <i>
+        <sc ts="12.5" user="A&amp;B" price="30" time="60" raw="{}">主播好</sc>
+        <d p="13.0,1,25,16777215,1647777083220,0,398452452,0" user="小马">快快快</d>
+        <sc ts="14.0" user="C" price="50" time="60" raw="{}"></sc>
+        <sc ts="15.0" user="D" price="100" time="60" raw="{}" />
+        </i>
+        "#;
+        let danmus = Parser::new(SUPER_CHAT.as_bytes())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(danmus.len(), 4);
+        assert_eq!(danmus[0].timeline_s, 12.5);
+        assert_eq!(danmus[0].content, "0-主播好");
+        assert_eq!(
+            danmus[0].r#type,
+            DanmuType::SuperChat {
+                user: "A&B".to_string(),
+                price: 30
+            }
+        );
+        assert_eq!(danmus[1].r#type, DanmuType::Float);
+        // 没有内容的醒目留言
+        for (danmu, (ts, price)) in danmus[2..].iter().zip([(14.0, 50), (15.0, 100)]) {
+            assert_eq!(danmu.timeline_s, ts);
+            assert!(danmu.content.is_empty(), "{}", danmu.content);
+            assert!(matches!(danmu.r#type, DanmuType::SuperChat { price: p, .. } if p == price));
+        }
+    }
+
+    #[test]
+    fn parse_gifts() {
+        let danmus = Parser::new(DATA.as_bytes())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(danmus.len(), 5);
+
+        let danmus = Parser::new(DATA.as_bytes())
+            .parse_gifts(true)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(danmus.len(), 8);
+        assert_eq!(danmus[0].timeline_s, 0.576);
+        assert_eq!(
+            danmus[0].r#type,
+            DanmuType::Gift {
+                user: "粉色羽毛球_Official".to_string(),
+                gift_name: "小心心".to_string(),
+                count: 1
+            }
+        );
+
+        let guard =
+            Parser::new(r#"<i><guard ts="1.5" user="A" level="3" count="2" /></i>"#.as_bytes())
+                .parse_gifts(true)
+                .next()
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            guard.r#type,
+            DanmuType::Guard {
+                user: "A".to_string(),
+                level: 3,
+                count: 2
+            }
+        );
+    }
+
     #[test]
     fn parse_rgb_255255255() {
         let danmu = Danmu::from_xml_p_attr(